@@ -3,7 +3,11 @@
 #![allow(async_fn_in_trait)]
 #![feature(type_alias_impl_trait)]
 
-use comms::{messages::command_router, server::run as websocket_server};
+use comms::{
+    router::{command_router, RouterConfig},
+    server::run as websocket_server,
+    telemetry::{self, TELEMETRY_PERIOD},
+};
 use embassy_executor::Spawner;
 use embassy_net::{driver::Driver, Config, Ipv4Address, Ipv4Cidr, Stack, StackResources};
 use esp_hal::entry;
@@ -13,6 +17,12 @@ use static_cell::{make_static, StaticCell};
 #[embassy_executor::task]
 async fn net_task(stack: &'static Stack<impl Driver>) -> ! { stack.run().await }
 
+#[embassy_executor::task]
+async fn telemetry_task(stack: &'static Stack<impl Driver>) -> !
+{
+    telemetry::publish(stack, TELEMETRY_PERIOD).await
+}
+
 #[main]
 async fn main(spawner: Spawner)
 {
@@ -38,6 +48,7 @@ async fn main(spawner: Spawner)
 
     // Launch network task
     spawner.spawn(net_task(stack)).unwrap();
+    spawner.spawn(telemetry_task(stack)).unwrap();
     spawner.spawn(connection()).unwrap();
 
     tracing::info!("Starting WebSocket comms");
@@ -50,5 +61,7 @@ async fn main(spawner: Spawner)
     )
     .await;
 
-    spawner.spawn(command_router()).unwrap();
+    spawner
+        .spawn(command_router(RouterConfig::default()))
+        .unwrap();
 }
@@ -1,5 +1,9 @@
 use clap::Parser;
-use comms::{messages::command_router, server::run as websocket_server};
+use comms::{
+    router::{command_router, RouterConfig},
+    server::run as websocket_server,
+    telemetry::{self, TELEMETRY_PERIOD},
+};
 use embassy_executor::{Executor, Spawner};
 use embassy_net::{Config, Ipv4Address, Ipv4Cidr, Stack, StackResources};
 use embassy_net_tuntap::TunTapDevice;
@@ -23,6 +27,12 @@ struct Opts
 #[embassy_executor::task]
 async fn net_task(stack: &'static Stack<TunTapDevice>) -> ! { stack.run().await }
 
+#[embassy_executor::task]
+async fn telemetry_task(stack: &'static Stack<TunTapDevice>) -> !
+{
+    telemetry::publish(stack, TELEMETRY_PERIOD).await
+}
+
 #[embassy_executor::task]
 async fn main_task(spawner: Spawner)
 {
@@ -61,6 +71,7 @@ async fn main_task(spawner: Spawner)
 
     // Launch network task
     spawner.spawn(net_task(stack)).unwrap();
+    spawner.spawn(telemetry_task(stack)).unwrap();
 
     info!("Starting WebSocket comms on port 8000");
 
@@ -87,6 +98,8 @@ fn main()
 
     executor.run(|spawner| {
         spawner.spawn(main_task(spawner)).unwrap();
-        spawner.spawn(command_router()).unwrap();
+        spawner
+            .spawn(command_router(RouterConfig::default()))
+            .unwrap();
     });
 }
@@ -3,7 +3,8 @@
 //! This library provides the implementation for a WebSocket comms and
 //! message handling system, designed to operate in a `no_std` environment
 //! when compiled with specific features and target configurations.
-//! It includes modules for comms functionality and message processing.
+//! It includes modules for comms functionality, message processing and
+//! telemetry.
 
 #![allow(unexpected_cfgs, unused_qualifications)]
 #![no_std]
@@ -27,3 +28,17 @@ pub mod server;
 /// a task for routing commands to appropriate handlers based on the message
 /// type.
 pub mod messages;
+
+/// Router Module
+///
+/// This module contains the command router, which takes the requests passed
+/// in by the WebSocket comms and runs them on the actuators. It also defines
+/// the router's configuration, and the task that runs it on the board.
+pub mod router;
+
+/// Telemetry Module
+///
+/// This module pushes the state of the robot to connected clients at a fixed
+/// rate. It includes the telemetry frames, the topics clients subscribe to,
+/// and the publisher that samples the robot and network state.
+pub mod telemetry;
@@ -2,73 +2,872 @@
 //!
 //! This module handles the message passing mechanism for the WebSocket comms.
 //! It defines the structure of the messages and provides a channel for sending
-//! and receiving commands to control hardware components such as motors and
-//! servos.
+//! and receiving commands to control hardware components such as motors,
+//! servos and fire control. The messages are handled by the
+//! [Router](crate::router::Router).
 
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
-use hardware::{mcu::init_mcu, Motor, MotorCommand, Servo, ServoCommand};
+use core::{cell::Cell, fmt, ptr};
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    channel::{Channel, TrySendError},
+    pubsub::PubSubChannel,
+    signal::Signal,
+};
+use hardware::{
+    estop::EStopSource,
+    fire::{FireMotor, JamEvent, MagazineEvent},
+    motor::MotorFault,
+    servo::LimitViolation,
+    FireCommand,
+    MotorCommand,
+    ServoCommand,
+};
+
+use crate::telemetry::{Telemetry, Topic};
 
 /// Global Channel for WebSocket Messages
 ///
-/// This static channel is used to send and receive `WebSocketMessage`
-/// instances. It employs a `CriticalSectionRawMutex` for synchronization and
-/// has a capacity of 64 messages.
-pub static CHANNEL: Channel<CriticalSectionRawMutex, WebSocketMessage, 64> = Channel::new();
+/// This static channel is used to send and receive incoming [Request]s. It
+/// employs a `CriticalSectionRawMutex` for synchronization and has a capacity
+/// of 64 messages.
+pub static CHANNEL: Channel<CriticalSectionRawMutex, Request, 64> = Channel::new();
+
+/// Global Channel for Outgoing Events
+///
+/// This static publish/subscribe channel carries `WebSocketMessage` instances
+/// from the router back to the clients. Every open WebSocket subscribes to it,
+/// so each event is delivered to all connected clients. It holds up to 16
+/// events for up to 4 subscribers; when full, the oldest event is dropped.
+/// Answers to requests are not sent here, but only to the client that sent
+/// the request, through its [Replies].
+pub static EVENTS: PubSubChannel<CriticalSectionRawMutex, WebSocketMessage, 16, 4, 1> =
+    PubSubChannel::new();
+
+/// Global Emergency Stop Signal
+///
+/// Raised by [emergency_stop], and watched by the router alongside the
+/// `CHANNEL`, so that an emergency stop never waits behind queued commands.
+pub static ESTOP: Signal<CriticalSectionRawMutex, Request> = Signal::new();
+
+/// Raise an emergency stop
+///
+/// Takes effect straight away, even while the router is busy or the
+/// `CHANNEL` is full. Also suitable as the `trip` callback of an
+/// [EStopInput](hardware::estop::EStopInput).
+pub fn emergency_stop(source: EStopSource)
+{
+    // the stop that is already waiting covers this one too
+    if ESTOP.signaled() {
+        return;
+    }
+
+    ESTOP.signal(Request {
+        id: None,
+        message: WebSocketMessage::EStop { source },
+        reply: None,
+    });
+}
+
+/// Global Controller Link Signal
+///
+/// Raised for every message from the controlling connection, or from any
+/// connection while none is in control, to feed the router's deadman.
+/// Traffic from other clients, such as a dashboard's heartbeats, never holds
+/// the deadman off.
+pub static LINK: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Global Controller Lost Signal
+///
+/// Raised when the controlling connection closes, or the last one does. It
+/// is kept apart from [LINK], so that the loss stays raised until the
+/// deadman sees it, however much traffic arrives in the meantime.
+pub static LOST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The connection that most recently sent a command to the router, whose
+/// loss trips the deadman
+static CONTROLLER: Mutex<CriticalSectionRawMutex, Cell<Option<ReplyTo>>> =
+    Mutex::new(Cell::new(None));
+
+/// Report that a client's WebSocket has closed
+///
+/// Trips the deadman straight away, rather than waiting for the timeout, if
+/// the connection was the one controlling the robot, or if it was the last
+/// one. Other clients, such as a dashboard watching telemetry, come and go
+/// without stopping anything.
+///
+/// # Parameters
+///
+/// - `connection`: Where the closed connection's requests were answered.
+/// - `remaining`: How many connections are still open.
+pub fn connection_lost(
+    connection: Option<ReplyTo>,
+    remaining: u8,
+)
+{
+    let controlling = CONTROLLER.lock(|controller| {
+        let controlling = remaining == 0 || controller.get() == connection;
+        if controlling {
+            controller.set(None);
+        }
+        controlling
+    });
+
+    if controlling {
+        LOST.signal(());
+    }
+}
+
+/// Make the connection the controlling one, feeding the deadman
+fn take_control(connection: Option<ReplyTo>)
+{
+    CONTROLLER.lock(|controller| controller.set(connection));
+    LINK.signal(());
+}
+
+/// Hand an incoming request to the router
+///
+/// Requests from the controlling connection, or from any connection while
+/// none is in control, feed the deadman, and a connection whose command
+/// reaches the router takes control. Emergency stops skip the `CHANNEL` and
+/// are raised straight away, and heartbeats are answered here; every other
+/// request is queued in order, and answered by the router through the
+/// request's [ReplyTo] once it has been handled. Nothing here waits on the
+/// router: while the `CHANNEL` is full, or the client already has as many
+/// requests waiting as it may, requests are answered with
+/// [ErrorCode::QueueFull] instead, so the client's next message, such as an
+/// emergency stop, is still read straight away.
+///
+/// # Returns
+///
+/// * `Option<HandlerResponse>` - The answer to send back straight away, if the
+///   request was tagged with an ID and is not left for the router to answer.
+pub fn dispatch(request: Request) -> Option<HandlerResponse>
+{
+    let controlling = CONTROLLER.lock(|controller| {
+        controller
+            .get()
+            .is_none_or(|controller| Some(controller) == request.reply)
+    });
+
+    if controlling {
+        LINK.signal(());
+    }
+
+    let outcome = match request.message {
+        // the stop that is already waiting covers this one too
+        WebSocketMessage::EStop { .. } if ESTOP.signaled() => Ok(()),
+        WebSocketMessage::EStop { .. } => match request.reserve() {
+            true => {
+                take_control(request.reply);
+                ESTOP.signal(request);
+                return None;
+            }
+            // an emergency stop is never refused, even without room for the
+            // router's answer
+            false => {
+                take_control(request.reply);
+                ESTOP.signal(Request {
+                    reply: None,
+                    ..request
+                });
+                Ok(())
+            }
+        },
+        // subscriptions belong to the connection, and are kept by the server
+        WebSocketMessage::Heartbeat
+        | WebSocketMessage::Subscribe(_)
+        | WebSocketMessage::Unsubscribe(_) => Ok(()),
+        WebSocketMessage::ConnectionLost => {
+            tracing::warn!("Ignoring event sent by client: {:?}", request.message);
+            Err(ErrorCode::Unsupported)
+        }
+        _ if !request.reserve() => {
+            tracing::warn!("Dropped {:?}: too many requests waiting", request.message);
+            Err(ErrorCode::QueueFull)
+        }
+        _ => match CHANNEL.try_send(request) {
+            Ok(()) => {
+                take_control(request.reply);
+                return None;
+            }
+            Err(TrySendError::Full(request)) => {
+                tracing::warn!("Dropped {:?}: command queue full", request.message);
+                request.release();
+                Err(ErrorCode::QueueFull)
+            }
+        },
+    };
+
+    request.id.map(|id| HandlerResponse::new(Some(id), outcome))
+}
 
 /// WebSocket Message Enum
 ///
 /// This enum defines the different types of messages that can be received via
-/// WebSocket. It includes commands for motors, servos, and combined motor and
-/// servo commands.
+/// WebSocket. It includes commands for motors, servos, fire control, and
+/// combined motor and servo commands, as well as the events sent back to
+/// clients.
 ///
 /// # Variants
 ///
-/// - `Motor(MotorCommand)`: A command to control a motor.
+/// - `Motor(MotorCommand)`: A command to control the flywheels.
+///   - Ex: `{ "Motor": "On" }`
+/// - `DriveMotor { motor, command }`: A command to control either motor.
+///   - Ex: `{ "DriveMotor": { "motor": "Loader", "command": { "Reverse": 40 } }
+///     }`
 /// - `Servo(ServoCommand)`: A command to control a servo.
-/// - `MotorAndServo { motor, servo }`: A command that includes both motor and
-///   servo commands.
+/// - `Fire(FireCommand)`: A command to fire, or to spin the flywheels up or
+///   down.
+/// - `MotorAndServo { target, motor, servo }`: Aim, then fire: the servo
+///   command runs first, and the motor command runs on the `target` motor (the
+///   flywheels by default) once the turret is on target. The motor command is
+///   skipped if the aim fails or is cancelled.
+///   - Ex: `{ "MotorAndServo": { "motor": { "Launch": {} }, "servo": {
+///     "PanTilt": [30, 45] } } }`
+/// - `LimitViolation(LimitViolation)`: Sent to clients when a servo command was
+///   clamped or rejected by the soft limits.
+/// - `Magazine(MagazineEvent)`: Sent to clients when the magazine is reloaded,
+///   removed or runs empty.
+/// - `Jam(JamEvent)`: Sent to clients when the loader jams, and when the jam is
+///   acknowledged.
+/// - `MotorFault { motor, fault }`: Sent to clients when a motor is cut by its
+///   stall, over-current or thermal protection, or refuses a command because it
+///   is out of thermal budget. Overheating faults carry the budget left.
+/// - `ActuatorFault(ActuatorFault)`: Sent to the client whose command faulted
+///   an actuator, after its driver fails, such as on a PWM or GPIO error.
+///   Faulted actuators are also published on the `Faults` telemetry topic.
+/// - `EStop { source }`: Stop everything at once and latch the system stopped,
+///   skipping any queued commands. Sent back to clients once latched.
+///   - Ex: `{ "EStop": {} }`
+/// - `Reset`: Clear a latched emergency stop, and any faulted actuators. Sent
+///   back to clients once cleared.
+///   - Ex: `"Reset"`
+/// - `Rejected(RejectReason)`: Sent to the client whose command was rejected.
+///   - Ex: `{ "Rejected": "EStopLatched" }`
+/// - `Heartbeat`: Keeps the deadman from tripping while the controller is
+///   otherwise idle. Any other message from the controller does the same;
+///   heartbeats from other clients do not.
+///   - Ex: `"Heartbeat"`
+/// - `ConnectionLost`: Sent to clients when the controller went silent or
+///   disconnected, and every actuator was stopped.
+/// - `Arm { pin }`: Arm the launcher, so that motor and fire commands are
+///   accepted. Must carry the configured PIN, if there is one.
+///   - Ex: `{ "Arm": {} }`
+///   - Ex: `{ "Arm": { "pin": 1234 } }`
+/// - `Disarm`: Stop both motors and disarm the launcher.
+///   - Ex: `"Disarm"`
+/// - `ArmState(ArmState)`: Sent to clients whenever the launcher changes arm
+///   state.
+/// - `HandlerResponse(HandlerResponse)`: Sent to a client once a request it
+///   tagged with an ID has been handled, or when a frame could not be parsed.
+/// - `Subscribe(Topic)`: Start receiving a telemetry topic on this connection.
+///   - Ex: `{ "Subscribe": "Motors" }`
+/// - `Unsubscribe(Topic)`: Stop receiving a telemetry topic on this connection.
+///   - Ex: `{ "Unsubscribe": "Motors" }`
+/// - `Telemetry(Telemetry)`: Sent to clients subscribed to the frame's topic,
+///   periodically.
 #[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum WebSocketMessage
 {
     Motor(MotorCommand),
+    DriveMotor
+    {
+        motor: FireMotor,
+        command: MotorCommand,
+    },
     Servo(ServoCommand),
+    Fire(FireCommand),
     MotorAndServo
     {
+        #[serde(default)]
+        target: FireMotor,
         motor: MotorCommand,
         servo: ServoCommand,
     },
-    // HandlerResponse(String),
+    LimitViolation(LimitViolation),
+    Magazine(MagazineEvent),
+    Jam(JamEvent),
+    MotorFault
+    {
+        motor: FireMotor,
+        fault: MotorFault,
+    },
+    ActuatorFault(ActuatorFault),
+    EStop
+    {
+        #[serde(default)]
+        source: EStopSource,
+    },
+    Reset,
+    Rejected(RejectReason),
+    Heartbeat,
+    ConnectionLost,
+    Arm
+    {
+        #[serde(default)]
+        pin: Option<u32>,
+    },
+    Disarm,
+    ArmState(ArmState),
+    HandlerResponse(HandlerResponse),
+    Subscribe(Topic),
+    Unsubscribe(Topic),
+    Telemetry(Telemetry),
+}
+
+/// Request
+///
+/// An incoming message, and the ID the client tagged it with, if any. Clients
+/// send either the bare message, or the message wrapped in an envelope with an
+/// ID. Requests with an ID are answered with a `HandlerResponse` once they
+/// have been handled, sent only to the client that sent them.
+///   - Ex: `{ "id": 7, "message": { "Motor": "On" } }`
+///   - Ex: `{ "Motor": "On" }`
+///
+/// # Fields
+/// - `id`: The ID the client tagged the request with.
+/// - `message`: The message itself.
+/// - `reply`: Where to answer the request; filled in by the server for the
+///   connection the request arrived on, or `None` to leave it unanswered.
+#[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Request
+{
+    #[serde(default)]
+    pub id: Option<u32>,
+    pub message: WebSocketMessage,
+    #[serde(skip)]
+    pub reply: Option<ReplyTo>,
+}
+
+impl Request
+{
+    /// Parse a request from an incoming frame, with or without an envelope
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Request, HandlerResponse>` - The request, or a `Malformed`
+    ///   response to send back, carrying the ID if one could be found.
+    pub fn parse(data: &[u8]) -> Result<Self, HandlerResponse>
+    {
+        /// Just the ID of an envelope whose message could not be parsed
+        #[derive(serde::Deserialize)]
+        struct Tag
+        {
+            id: Option<u32>,
+        }
+
+        serde_json::from_slice::<Self>(data)
+            .or_else(|_| {
+                serde_json::from_slice(data).map(|message| Self {
+                    id: None,
+                    message,
+                    reply: None,
+                })
+            })
+            .map_err(|error| {
+                tracing::error!(?error, "error deserializing incoming message");
+                let id = serde_json::from_slice::<Tag>(data)
+                    .ok()
+                    .and_then(|tag| tag.id);
+                HandlerResponse::new(id, Err(ErrorCode::Malformed))
+            })
+    }
+
+    /// Tell the client that sent the request about something it caused
+    ///
+    /// Must come before the request is answered, while there is still room
+    /// held for it.
+    pub(crate) fn notify(
+        &self,
+        message: WebSocketMessage,
+    )
+    {
+        if let Some(reply) = self.reply {
+            reply.send(message);
+        }
+    }
+
+    /// Answer the request, if the client tagged it with an ID
+    ///
+    /// Also gives up the room held for the request's answers, so the router
+    /// calls this exactly once for every request it takes.
+    pub(crate) fn respond(
+        self,
+        outcome: Result<(), ErrorCode>,
+    )
+    {
+        if let Some(reply) = self.reply {
+            if self.id.is_some() {
+                reply.send(WebSocketMessage::HandlerResponse(HandlerResponse::new(
+                    self.id, outcome,
+                )));
+            }
+            reply.release();
+        }
+    }
+
+    /// Hold room for the request's answers, if it is to be answered
+    fn reserve(&self) -> bool { self.reply.is_none_or(ReplyTo::reserve) }
+
+    /// Give up the room held for the request's answers, without answering
+    fn release(&self)
+    {
+        if let Some(reply) = self.reply {
+            reply.release();
+        }
+    }
 }
 
-/// Command Router Task
+/// Requests a single connection may have waiting on the router at once
+const MAX_IN_FLIGHT: usize = 8;
+
+/// Most replies the router sends for a single request: an `ActuatorFault` for
+/// each actuator, or a `Rejected`, and its `HandlerResponse`
+const REPLIES_PER_REQUEST: usize = Actuator::ALL.len() + 1;
+
+/// Answers a single connection may have waiting to be sent
+const REPLY_DEPTH: usize = MAX_IN_FLIGHT * REPLIES_PER_REQUEST;
+
+/// Replies
+///
+/// Where the answers to one connection's requests wait to be sent, so that
+/// each client only receives the answers to its own requests. The server
+/// keeps one for each WebSocket; tests and other front ends can supply their
+/// own.
+///
+/// Answers are never dropped while the connection is open: a request is only
+/// handed to the router while there is room for every answer owed to the
+/// connection, including those not yet sent. Otherwise it is answered with
+/// [ErrorCode::QueueFull] straight away.
 ///
-/// This asynchronous task continuously listens for incoming `WebSocketMessage`
-/// instances from the `CHANNEL`. It routes the messages to the appropriate
-/// handlers based on their type.
-#[embassy_executor::task]
-pub async fn command_router()
+/// # Fields
+/// - `queue`: Answers waiting to be sent.
+/// - `state`: Which connection the replies are for, and how many of its
+///   requests the router holds.
+pub struct Replies
 {
-    let mcu = init_mcu();
-    let mut servos = mcu.servos;
-    let mut flywheels = mcu.flywheels;
+    queue: Channel<CriticalSectionRawMutex, WebSocketMessage, REPLY_DEPTH>,
+    state: Mutex<CriticalSectionRawMutex, Cell<ReplyState>>,
+}
+
+/// Reply State
+///
+/// # Fields
+/// - `generation`: Counts the connections that have used the replies, so that
+///   answers owed to one that has closed are never sent to the next.
+/// - `open`: Whether a connection is using the replies.
+/// - `in_flight`: How many of the connection's requests the router holds.
+#[derive(Copy, Clone)]
+struct ReplyState
+{
+    generation: u16,
+    open: bool,
+    in_flight: usize,
+}
+
+impl Replies
+{
+    /// Create a new `Replies`, not yet in use
+    pub const fn new() -> Self
+    {
+        Self {
+            queue: Channel::new(),
+            state: Mutex::new(Cell::new(ReplyState {
+                generation: 0,
+                open: false,
+                in_flight: 0,
+            })),
+        }
+    }
+
+    /// Start taking replies for a new connection
+    ///
+    /// # Returns
+    ///
+    /// * `Option<ReplyTo>` - Where the connection's requests are answered, or
+    ///   `None` if another connection is already using the replies.
+    pub fn open(&'static self) -> Option<ReplyTo>
+    {
+        self.state.lock(|state| {
+            let ReplyState {
+                generation, open, ..
+            } = state.get();
+
+            if open {
+                return None;
+            }
+
+            let generation = generation.wrapping_add(1);
+            state.set(ReplyState {
+                generation,
+                open: true,
+                in_flight: 0,
+            });
+            self.queue.clear();
 
-    loop {
-        match CHANNEL.receiver().receive().await {
-            WebSocketMessage::Motor(command) => {
-                tracing::info!("Received Motor Command: {:?}", command);
-                flywheels.process(command).await.unwrap();
+            Some(ReplyTo {
+                replies: self,
+                generation,
+            })
+        })
+    }
+
+    /// Update the state, if it still belongs to the given connection
+    fn update<R>(
+        &self,
+        generation: u16,
+        update: impl FnOnce(&mut ReplyState) -> R,
+    ) -> Option<R>
+    {
+        self.state.lock(|state| {
+            let mut current = state.get();
+
+            if !current.open || current.generation != generation {
+                return None;
             }
-            WebSocketMessage::Servo(command) => {
-                tracing::info!("Received Servo Command: {:?}", command);
-                servos.process(command).await.unwrap();
+
+            let result = update(&mut current);
+            state.set(current);
+            Some(result)
+        })
+    }
+}
+
+impl Default for Replies
+{
+    fn default() -> Self { Self::new() }
+}
+
+/// Reply Address
+///
+/// Where a request is answered: the [Replies] of the connection it arrived
+/// on, for as long as that connection stays open.
+///
+/// # Fields
+/// - `replies`: The connection's replies.
+/// - `generation`: The connection, among those that have used the replies.
+#[derive(Copy, Clone)]
+pub struct ReplyTo
+{
+    replies: &'static Replies,
+    generation: u16,
+}
+
+impl ReplyTo
+{
+    /// Wait for the next answer to send to the client
+    pub async fn receive(self) -> WebSocketMessage { self.replies.queue.receive().await }
+
+    /// Stop taking replies, once the connection has closed
+    ///
+    /// Answers still owed to the connection are dropped.
+    pub fn close(self)
+    {
+        self.replies.update(self.generation, |state| {
+            state.open = false;
+            state.in_flight = 0;
+        });
+        self.replies.queue.clear();
+    }
+
+    /// Hold room for the answers to one more request
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether there was room, or `false` if the connection has
+    ///   closed or is owed as many answers as there is room for.
+    fn reserve(self) -> bool
+    {
+        let replies = self.replies;
+
+        replies.update(self.generation, |state| {
+            let owed = replies.queue.len() + (state.in_flight + 1) * REPLIES_PER_REQUEST;
+
+            match owed <= REPLY_DEPTH {
+                true => state.in_flight += 1,
+                false => return false,
             }
-            WebSocketMessage::MotorAndServo { motor, servo } => {
-                tracing::info!(
-                    "Received Motor Command: {:?} and Servo Command: {:?}",
-                    motor,
-                    servo
-                );
+            true
+        }) == Some(true)
+    }
+
+    /// Send an answer, if the connection is still open
+    fn send(
+        self,
+        message: WebSocketMessage,
+    )
+    {
+        let replies = self.replies;
+
+        replies.update(self.generation, |_| {
+            if replies.queue.try_send(message).is_err() {
+                tracing::error!("No room to answer with {:?}", message);
             }
+        });
+    }
+
+    /// Give up the room held for a request's answers
+    fn release(self)
+    {
+        self.replies.update(self.generation, |state| {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        });
+    }
+}
+
+impl PartialEq for ReplyTo
+{
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool
+    {
+        ptr::eq(self.replies, other.replies) && self.generation == other.generation
+    }
+}
+
+impl Eq for ReplyTo {}
+
+impl fmt::Debug for ReplyTo
+{
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result
+    {
+        f.debug_struct("ReplyTo")
+            .field("generation", &self.generation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handler Response
+///
+/// The outcome of a request, sent once the router has finished with it. A
+/// move or launch is only answered once it has completed, or been stopped.
+///   - Ex: `{ "HandlerResponse": { "id": 7, "status": "Ok" } }`
+///   - Ex: `{ "HandlerResponse": { "id": 8, "status": "Error", "error":
+///     "Empty", "reason": "magazine empty" } }`
+///
+/// # Fields
+/// - `id`: The ID of the request, or `None` for a frame that could not be
+///   parsed.
+/// - `status`: Whether the request succeeded.
+/// - `error`: What went wrong, if it failed.
+/// - `reason`: A description of what went wrong, for people.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct HandlerResponse
+{
+    pub id: Option<u32>,
+    pub status: ResponseStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorCode>,
+    #[serde(skip_deserializing, skip_serializing_if = "str::is_empty")]
+    pub reason: &'static str,
+}
+
+impl HandlerResponse
+{
+    /// Create a new `HandlerResponse` from the outcome of a request
+    pub fn new(
+        id: Option<u32>,
+        outcome: Result<(), ErrorCode>,
+    ) -> Self
+    {
+        match outcome {
+            Ok(()) => Self {
+                id,
+                status: ResponseStatus::Ok,
+                error: None,
+                reason: "",
+            },
+            Err(error) => Self {
+                id,
+                status: ResponseStatus::Error,
+                error: Some(error),
+                reason: error.reason(),
+            },
+        }
+    }
+}
+
+/// Response Status
+///
+/// Variants:
+/// - `Ok`: The request was handled successfully.
+/// - `Error`: The request failed, or was refused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ResponseStatus
+{
+    Ok,
+    Error,
+}
+
+/// Error Code
+///
+/// Why a request failed.
+///
+/// Variants:
+/// - `Malformed`: The frame could not be parsed.
+/// - `Unsupported`: The message is an event, and can't be sent by clients.
+/// - `EStopLatched`: An emergency stop is latched, and must be reset first.
+/// - `Disarmed`: The launcher must be armed first.
+/// - `WrongPin`: An `Arm` message carried the wrong PIN, or none.
+/// - `Superseded`: The command was stopped early by a newer one.
+/// - `ControllerLost`: The command was dropped, as the controller went silent.
+/// - `SpinUpTimeout`: The flywheels did not come up to speed.
+/// - `Empty`: The magazine is empty.
+/// - `Jammed`: The loader is jammed.
+/// - `MotorFault`: A motor was cut by its protection.
+/// - `OutOfLimits`: The servo command was rejected by the soft limits.
+/// - `ActuatorFault(Actuator)`: The actuator's driver failed, or it is faulted
+///   and must be reset first.
+///   - Ex: `{ "ActuatorFault": "Loader" }`
+/// - `QueueFull`: Too many commands are waiting behind the one running.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ErrorCode
+{
+    Malformed,
+    Unsupported,
+    EStopLatched,
+    Disarmed,
+    WrongPin,
+    Superseded,
+    ControllerLost,
+    SpinUpTimeout,
+    Empty,
+    Jammed,
+    MotorFault,
+    OutOfLimits,
+    ActuatorFault(Actuator),
+    QueueFull,
+}
+
+impl ErrorCode
+{
+    /// A description of the error, for people
+    pub const fn reason(self) -> &'static str
+    {
+        match self {
+            Self::Malformed => "message could not be parsed",
+            Self::Unsupported => "events can't be sent by clients",
+            Self::EStopLatched => "emergency stop latched",
+            Self::Disarmed => "launcher disarmed",
+            Self::WrongPin => "wrong PIN",
+            Self::Superseded => "stopped by a newer command",
+            Self::ControllerLost => "controller lost",
+            Self::SpinUpTimeout => "flywheels not up to speed",
+            Self::Empty => "magazine empty",
+            Self::Jammed => "loader jammed",
+            Self::MotorFault => "motor stopped by its protection",
+            Self::OutOfLimits => "servo soft limits exceeded",
+            Self::ActuatorFault(_) => "actuator faulted",
+            Self::QueueFull => "command queue full",
+        }
+    }
+}
+
+impl From<RejectReason> for ErrorCode
+{
+    fn from(reason: RejectReason) -> Self
+    {
+        match reason {
+            RejectReason::EStopLatched => Self::EStopLatched,
+            RejectReason::Disarmed => Self::Disarmed,
+            RejectReason::WrongPin => Self::WrongPin,
+            RejectReason::Faulted(actuator) => Self::ActuatorFault(actuator),
+            RejectReason::QueueFull => Self::QueueFull,
+        }
+    }
+}
+
+/// Reject Reason
+///
+/// Why a command was rejected.
+///
+/// Variants:
+/// - `EStopLatched`: An emergency stop is latched, and must be reset first.
+/// - `Disarmed`: The launcher must be armed first.
+///   - Ex: `{ "Rejected": "Disarmed" }`
+/// - `WrongPin`: An `Arm` message carried the wrong PIN, or none.
+/// - `Faulted(Actuator)`: The command drives a faulted actuator, which must be
+///   reset first.
+///   - Ex: `{ "Rejected": { "Faulted": "Servos" } }`
+/// - `QueueFull`: Too many commands are waiting behind the one running.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RejectReason
+{
+    EStopLatched,
+    Disarmed,
+    WrongPin,
+    Faulted(Actuator),
+    QueueFull,
+}
+
+/// Actuator
+///
+/// Variants:
+/// - `Flywheels`: The flywheel motor.
+/// - `Loader`: The loader motor.
+/// - `Servos`: The pan and tilt servos.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Actuator
+{
+    Flywheels,
+    Loader,
+    Servos,
+}
+
+impl Actuator
+{
+    /// Every actuator
+    pub(crate) const ALL: [Self; 3] = [Self::Flywheels, Self::Loader, Self::Servos];
+}
+
+impl From<FireMotor> for Actuator
+{
+    fn from(motor: FireMotor) -> Self
+    {
+        match motor {
+            FireMotor::Flywheels => Self::Flywheels,
+            FireMotor::Loader => Self::Loader,
         }
     }
 }
+
+/// Actuator Fault
+///
+/// A driver error from an actuator, such as a failed PWM or GPIO write. The
+/// actuator is marked faulted, and commands that drive it are rejected until a
+/// `Reset`.
+///   - Ex: `{ "ActuatorFault": { "actuator": "Loader", "errors": 1 } }`
+///
+/// # Fields
+/// - `actuator`: The actuator that failed.
+/// - `errors`: How many driver errors the actuator has had since boot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ActuatorFault
+{
+    pub actuator: Actuator,
+    pub errors: u32,
+}
+
+/// Arm State
+///
+/// Variants:
+/// - `Disarmed`: Motor and fire commands are rejected, other than those that
+///   stop the motors.
+///   - Ex: `{ "ArmState": "Disarmed" }`
+/// - `Armed`: Motor and fire commands are accepted.
+///   - Ex: `{ "ArmState": "Armed" }`
+/// - `Firing`: A fire command is running.
+///   - Ex: `{ "ArmState": "Firing" }`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ArmState
+{
+    #[default]
+    Disarmed,
+    Armed,
+    Firing,
+}
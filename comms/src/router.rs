@@ -0,0 +1,1294 @@
+//! ## Router Module
+//!
+//! Routes incoming [Request](crate::messages::Request)s to the actuators they
+//! command, one at a time. The [Router] looks after arming, the deadman and
+//! actuator faults, and answers every request once it has been handled.
+
+use embassy_futures::select::{select4, Either4};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Ticker};
+use hardware::{
+    estop::EStopSource,
+    fire::{FireConfig, FireError, FireMotor, JamSensor, MagazineEvent, MagazineSensor},
+    motor::{CurrentLimits, HeatModel, ThermalPolicy},
+    FireCommand,
+    FireControl,
+    Motor,
+    MotorCommand,
+    Servo,
+    ServoCommand,
+};
+
+use self::{arming::Arming, health::Health, inbox::Inbox};
+use crate::{
+    messages::{
+        Actuator,
+        ArmState,
+        ErrorCode,
+        RejectReason,
+        Request,
+        WebSocketMessage,
+        CHANNEL,
+        ESTOP,
+        EVENTS,
+        LINK,
+        LOST,
+    },
+    telemetry::{self, RobotState},
+};
+
+mod arming;
+mod deadman;
+mod health;
+mod inbox;
+
+/// Default Deadman Timeout
+///
+/// How long the controller may stay silent before the router stops
+/// everything. Clients should send a `Heartbeat` at least twice as often
+/// while idle.
+pub const DEADMAN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default Magazine Capacity
+///
+/// Rounds in a full magazine, unless [RouterConfig::magazine] says otherwise.
+pub const MAGAZINE_CAPACITY: u16 = 12;
+
+/// Router Config
+///
+/// # Fields
+/// - `deadman`: How long the controller may stay silent before the router stops
+///   everything.
+/// - `pin`: The PIN an `Arm` message must carry, or `None` to arm without one.
+/// - `arm_timeout`: How long the launcher stays armed without a motor or fire
+///   command before it disarms itself.
+/// - `telemetry`: How often the router samples the robot's state for telemetry
+///   while it is idle.
+/// - `magazine`: Rounds in a full magazine.
+/// - `fire`: Spin-up, shot and jam-clearing timing for fire control.
+/// - `flywheel_current`: The stall and over-current limits for the flywheels.
+/// - `loader_current`: The stall and over-current limits for the loader.
+/// - `flywheel_heat`: How quickly the flywheels heat up and cool down.
+/// - `loader_heat`: How quickly the loader heats up and cools down.
+/// - `thermal`: What either motor does with a command once it is out of thermal
+///   budget.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RouterConfig
+{
+    pub deadman: Duration,
+    pub pin: Option<u32>,
+    pub arm_timeout: Duration,
+    pub telemetry: Duration,
+    pub magazine: u16,
+    pub fire: FireConfig,
+    pub flywheel_current: CurrentLimits,
+    pub loader_current: CurrentLimits,
+    pub flywheel_heat: HeatModel,
+    pub loader_heat: HeatModel,
+    pub thermal: ThermalPolicy,
+}
+
+impl Default for RouterConfig
+{
+    /// No arming PIN, a one minute arming timeout, and a 12 round magazine
+    ///
+    /// The loader, typically a solenoid, may run flat out for 10 seconds from
+    /// cold, and the flywheels for a minute. Commands beyond that are held
+    /// back until the motor has cooled.
+    fn default() -> Self
+    {
+        Self {
+            deadman: DEADMAN_TIMEOUT,
+            pin: None,
+            arm_timeout: Duration::from_secs(60),
+            telemetry: telemetry::TELEMETRY_PERIOD,
+            magazine: MAGAZINE_CAPACITY,
+            fire: FireConfig::default(),
+            flywheel_current: CurrentLimits::default(),
+            loader_current: CurrentLimits::default(),
+            flywheel_heat: HeatModel::new(Duration::from_secs(60), Duration::from_secs(120)),
+            loader_heat: HeatModel::new(Duration::from_secs(10), Duration::from_secs(30)),
+            thermal: ThermalPolicy::Throttle,
+        }
+    }
+}
+
+/// Command Source
+///
+/// Where a [Router] receives its requests from. [CommandSource::global] is
+/// fed by the WebSocket server; tests and other front ends can supply their
+/// own.
+///
+/// # Fields
+/// - `requests`: Requests handled in order.
+/// - `estop`: Emergency stops, handled ahead of any queued requests.
+/// - `link`: The controller's activity, which feeds the deadman.
+/// - `lost`: The loss of the controller's connection, which trips the deadman.
+#[derive(Copy, Clone)]
+pub struct CommandSource<'a>
+{
+    pub requests: &'a Channel<CriticalSectionRawMutex, Request, 64>,
+    pub estop: &'a Signal<CriticalSectionRawMutex, Request>,
+    pub link: &'a Signal<CriticalSectionRawMutex, ()>,
+    pub lost: &'a Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl CommandSource<'static>
+{
+    /// The `CHANNEL`, `ESTOP`, `LINK` and `LOST` fed by the WebSocket server
+    pub const fn global() -> Self
+    {
+        Self {
+            requests: &CHANNEL,
+            estop: &ESTOP,
+            link: &LINK,
+            lost: &LOST,
+        }
+    }
+}
+
+/// Reject a request, telling the client that sent it why
+fn reject(
+    request: Request,
+    reason: RejectReason,
+)
+{
+    tracing::warn!(?reason, "Rejected {:?}", request.message);
+    request.notify(WebSocketMessage::Rejected(reason));
+    request.respond(Err(reason.into()));
+}
+
+/// Report a magazine change to connected clients
+fn publish_magazine(event: MagazineEvent)
+{
+    tracing::info!(?event, "Magazine changed");
+    EVENTS
+        .immediate_publisher()
+        .publish_immediate(WebSocketMessage::Magazine(event));
+}
+
+/// The motor command the message carries, for either motor
+fn motor_command(message: &WebSocketMessage) -> Option<MotorCommand>
+{
+    match *message {
+        WebSocketMessage::Motor(command)
+        | WebSocketMessage::DriveMotor { command, .. }
+        | WebSocketMessage::MotorAndServo { motor: command, .. } => Some(command),
+        _ => None,
+    }
+}
+
+/// Whether the message should abort a long-running motor operation
+///
+/// Any motor command that stops a motor (`Off`, `Abort`, `Brake` or
+/// `Coast`), a `SpinDown` or a `Disarm`, preempts the operation immediately,
+/// rather than waiting behind it in the `CHANNEL`.
+fn aborts(message: &WebSocketMessage) -> bool
+{
+    matches!(
+        motor_command(message),
+        Some(MotorCommand::Off | MotorCommand::Abort | MotorCommand::Brake | MotorCommand::Coast)
+    ) || matches!(
+        message,
+        WebSocketMessage::Fire(FireCommand::SpinDown) | WebSocketMessage::Disarm
+    )
+}
+
+/// Whether the message needs the launcher armed
+///
+/// Every motor and fire command does, other than those that stop the motors,
+/// which are always accepted.
+fn needs_arming(message: &WebSocketMessage) -> bool
+{
+    match message {
+        WebSocketMessage::Fire(FireCommand::Cease) => false,
+        WebSocketMessage::Motor(_)
+        | WebSocketMessage::DriveMotor { .. }
+        | WebSocketMessage::Fire(_)
+        | WebSocketMessage::MotorAndServo { .. } => !aborts(message),
+        _ => false,
+    }
+}
+
+/// Whether the message drives the actuator
+///
+/// Commands that stop the motors, and fire commands that don't run them,
+/// don't count, so that they are accepted while the actuator is faulted. A
+/// `MotorAndServo` still drives the servos when its motor command stops the
+/// motor.
+fn drives(
+    message: &WebSocketMessage,
+    actuator: Actuator,
+) -> bool
+{
+    match *message {
+        WebSocketMessage::MotorAndServo { target, .. } => {
+            actuator == Actuator::Servos || (actuator == target.into() && !aborts(message))
+        }
+        _ if aborts(message) => false,
+        WebSocketMessage::Motor(_) => actuator == Actuator::Flywheels,
+        WebSocketMessage::DriveMotor { motor, .. } => actuator == motor.into(),
+        WebSocketMessage::Servo(_) => actuator == Actuator::Servos,
+        WebSocketMessage::Fire(
+            FireCommand::Single | FireCommand::Burst(_) | FireCommand::Auto | FireCommand::SpinUp,
+        ) => actuator != Actuator::Servos,
+        _ => false,
+    }
+}
+
+/// Command Router
+///
+/// Continuously listens for incoming [Request]s from a [CommandSource], and
+/// routes the messages to the appropriate handlers based on their type. The
+/// router takes whatever motors, sensors and servos it is given, so that the
+/// whole command path can be driven by mock actuators on the host. Events for
+/// every client are published on [EVENTS], answers are sent to the client
+/// that made the request, and the robot's state is shared on
+/// [telemetry::STATE].
+///
+/// Servo moves are cancelled as soon as a newer servo command (or an `Abort`)
+/// arrives, so the turret always heads for the most recent target. Motor
+/// operations, such as a launch, are cancelled by any command that stops the
+/// motor, after which the motor is turned off and the stopping command runs.
+///
+/// The flywheels and the loader are only driven through [FireControl], which
+/// also spins the flywheels down while the router waits for the next message.
+/// `Motor` commands drive the flywheels, and `DriveMotor` commands whichever
+/// motor they address. A `MotorAndServo` aims first, and only runs its motor
+/// command once the turret is on target.
+/// Firing stops at the next `Cease` or stopping command, and full-auto stops
+/// as soon as any other message arrives; the interrupting message then runs
+/// as usual. Fire commands are refused while the magazine is empty, and every
+/// magazine change is reported to clients as a `Magazine` event. A loader
+/// jam stops firing and is reported as a `Jam` event, and fire commands are
+/// refused until it is acknowledged. Motors cut by their current or thermal
+/// protection stop fire control, and are reported as a `MotorFault` event.
+///
+/// An emergency stop cancels whatever is running, turns off both motors,
+/// releases the servos and latches the system stopped. Every command is then
+/// rejected with [RejectReason::EStopLatched] until a `Reset`.
+///
+/// If no message arrives from the controller within the
+/// [RouterConfig::deadman] timeout, or its WebSocket closes, whatever is
+/// running is cancelled, both motors are turned
+/// off and any commands still queued are dropped. The servos hold where they
+/// are. Clients are told with a `ConnectionLost` event, and the next command
+/// runs as usual.
+///
+/// The launcher starts disarmed, and motor and fire commands are rejected
+/// with [RejectReason::Disarmed] until an `Arm` message carrying the
+/// configured PIN arrives. Commands that stop the motors are always accepted.
+/// The launcher disarms itself after going without a motor or fire command
+/// for the arming timeout, and on a `Disarm`, an emergency stop or the
+/// deadman tripping; disarming stops both motors. Every change of arm state
+/// is reported to clients as an `ArmState` event.
+///
+/// The robot's state is shared with the telemetry publisher after every
+/// message, and every [RouterConfig::telemetry] period while idle.
+///
+/// Every request tagged with an ID is answered with a `HandlerResponse` once
+/// it has been handled, so a move or launch is only answered once it has
+/// finished or been stopped. The answer goes only to the client that sent the
+/// request, through its [ReplyTo](crate::messages::ReplyTo).
+///
+/// A driver error from any actuator, such as a failed PWM or GPIO write,
+/// never stops the router. It is logged, counted and reported as an
+/// `ActuatorFault` event to the client whose request hit it, which is answered
+/// with
+/// [ErrorCode::ActuatorFault]. Both motors are stopped after either of them
+/// fails. The actuator is left faulted, and commands that drive it are
+/// rejected with [RejectReason::Faulted] until a `Reset`.
+///
+/// # Type Parameters
+/// - `F`: The flywheel motor.
+/// - `L`: The loader motor.
+/// - `S`: The magazine sensor, or `()` for none.
+/// - `J`: The jam sensor, or `()` for none.
+/// - `V`: The pan and tilt servos.
+///
+/// # Fields
+/// - `fire`: Fire control, driving the flywheels and the loader.
+/// - `servos`: The pan and tilt servos.
+/// - `inbox`: Where requests come from.
+/// - `arming`: The arm state.
+/// - `sampler`: When to next sample the robot's state while idle.
+/// - `latched`: The source of a latched emergency stop, until reset.
+/// - `health`: Driver errors and faulted actuators.
+pub struct Router<'a, F: Motor, L: Motor, S: MagazineSensor, J: JamSensor, V: Servo>
+{
+    fire: FireControl<F, L, S, J>,
+    servos: V,
+    inbox: Inbox<'a>,
+    arming: Arming,
+    sampler: Ticker,
+    latched: Option<EStopSource>,
+    health: Health,
+}
+
+impl<'a, F: Motor, L: Motor, S: MagazineSensor, J: JamSensor, V: Servo> Router<'a, F, L, S, J, V>
+{
+    /// Create a new `Router` from the supplied actuators and source
+    pub fn new(
+        fire: FireControl<F, L, S, J>,
+        servos: V,
+        source: CommandSource<'a>,
+        config: RouterConfig,
+    ) -> Self
+    {
+        Self {
+            fire,
+            servos,
+            inbox: Inbox::new(source, config.deadman),
+            arming: Arming::new(config.pin, config.arm_timeout),
+            sampler: Ticker::every(config.telemetry),
+            latched: None,
+            health: Health::default(),
+        }
+    }
+
+    /// Route requests until the end of time
+    pub async fn run(mut self) -> !
+    {
+        loop {
+            telemetry::STATE.sender().send(RobotState::sample(
+                self.servos.position(),
+                &self.fire,
+                self.arming.state(),
+                self.latched.is_some(),
+                |actuator| self.health.is_faulted(actuator),
+            ));
+
+            match self.next().await {
+                Some(request) => self.handle(request).await,
+                None => self.health.report(None),
+            }
+        }
+    }
+
+    /// Wait for the next request, looking after the motors in the meantime
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Request>` - The next request, or `None` if something else
+    ///   needed attention first.
+    async fn next(&mut self) -> Option<Request>
+    {
+        match select4(
+            self.inbox.receive(),
+            self.fire.idle(),
+            self.arming.expired(),
+            self.sampler.next(),
+        )
+        .await
+        {
+            Either4::First(request) => Some(request),
+            Either4::Second(result) => {
+                // trips are reported here rather than treated as errors
+                if !self.report_faults() {
+                    match result {
+                        Ok(Some(event)) => publish_magazine(event),
+                        Ok(None) => tracing::info!("Flywheels spun down"),
+                        Err(error) => {
+                            self.fire_error(error);
+                        }
+                    }
+                }
+                None
+            }
+            Either4::Third(()) => {
+                tracing::info!("Disarming after idle timeout");
+                self.stop();
+
+                self.arming.set(ArmState::Disarmed);
+                None
+            }
+            Either4::Fourth(()) => None,
+        }
+    }
+
+    /// Handle a single request, and answer it
+    async fn handle(
+        &mut self,
+        request: Request,
+    )
+    {
+        let message = request.message;
+
+        if self.latched.is_some() {
+            match message {
+                WebSocketMessage::Reset => {
+                    tracing::warn!("Emergency stop reset");
+                    self.latched = None;
+                    self.health.reset();
+                    EVENTS
+                        .immediate_publisher()
+                        .publish_immediate(WebSocketMessage::Reset);
+                    request.respond(Ok(()));
+                }
+                // the stop is already latched, such as while the e-stop input
+                // is held, and everything is already off
+                WebSocketMessage::EStop { .. } | WebSocketMessage::ConnectionLost => {
+                    request.respond(Ok(()));
+                }
+                _ => reject(request, RejectReason::EStopLatched),
+            }
+            return;
+        }
+
+        if !self.arming.is_armed() && needs_arming(&message) {
+            reject(request, RejectReason::Disarmed);
+            return;
+        }
+
+        if let Some(actuator) = self.health.blocks(&message) {
+            reject(request, RejectReason::Faulted(actuator));
+            return;
+        }
+
+        let outcome = match message {
+            WebSocketMessage::EStop { source } => {
+                tracing::warn!(?source, "Emergency stop");
+                self.stop();
+
+                if let Err(error) = self.servos.detach() {
+                    self.health.record(Actuator::Servos, error);
+                }
+
+                self.arming.set(ArmState::Disarmed);
+                self.latched = Some(source);
+                EVENTS
+                    .immediate_publisher()
+                    .publish_immediate(WebSocketMessage::EStop { source });
+                Ok(())
+            }
+            WebSocketMessage::Reset => {
+                match self.health.reset() {
+                    true => {
+                        tracing::warn!("Actuator faults reset");
+                        EVENTS
+                            .immediate_publisher()
+                            .publish_immediate(WebSocketMessage::Reset);
+                    }
+                    false => tracing::info!("Ignoring Reset: nothing to reset"),
+                }
+                Ok(())
+            }
+            WebSocketMessage::ConnectionLost => {
+                tracing::warn!("Controller lost, stopping everything");
+
+                // commands queued before the loss must not run unattended
+                self.inbox.drain();
+                self.stop();
+
+                self.arming.set(ArmState::Disarmed);
+                EVENTS
+                    .immediate_publisher()
+                    .publish_immediate(WebSocketMessage::ConnectionLost);
+                Ok(())
+            }
+            WebSocketMessage::Arm { pin } => match self.arming.accepts(pin) {
+                true => {
+                    self.arming.set(ArmState::Armed);
+                    Ok(())
+                }
+                false => {
+                    reject(request, RejectReason::WrongPin);
+                    return;
+                }
+            },
+            WebSocketMessage::Disarm => {
+                tracing::info!("Received Disarm");
+                self.stop();
+                self.arming.set(ArmState::Disarmed);
+                Ok(())
+            }
+            // these are answered in `dispatch`
+            WebSocketMessage::Heartbeat
+            | WebSocketMessage::Subscribe(_)
+            | WebSocketMessage::Unsubscribe(_) => Ok(()),
+            WebSocketMessage::Motor(command) => {
+                self.motor_command(FireMotor::Flywheels, command).await
+            }
+            WebSocketMessage::DriveMotor { motor, command } => {
+                self.motor_command(motor, command).await
+            }
+            WebSocketMessage::Fire(command) => self.fire_command(command).await,
+            WebSocketMessage::Servo(command) => self.servo_command(command).await,
+            WebSocketMessage::MotorAndServo {
+                target,
+                motor,
+                servo,
+            } => {
+                tracing::info!(
+                    "Received Motor Command: {:?} and Servo Command: {:?}",
+                    motor,
+                    servo
+                );
+
+                // aim, then fire
+                match self.servo_command(servo).await {
+                    Ok(()) => self.motor_command(target, motor).await,
+                    Err(error) => {
+                        tracing::info!("Motor Command {:?} skipped: aim failed", motor);
+                        Err(error)
+                    }
+                }
+            }
+            WebSocketMessage::LimitViolation(_)
+            | WebSocketMessage::Magazine(_)
+            | WebSocketMessage::Jam(_)
+            | WebSocketMessage::MotorFault { .. }
+            | WebSocketMessage::ActuatorFault(_)
+            | WebSocketMessage::Rejected(_)
+            | WebSocketMessage::ArmState(_)
+            | WebSocketMessage::HandlerResponse(_)
+            | WebSocketMessage::Telemetry(_) => {
+                tracing::warn!("Ignoring event sent by client: {:?}", message);
+                Err(ErrorCode::Unsupported)
+            }
+        };
+
+        self.health.report(Some(&request));
+        request.respond(outcome);
+    }
+
+    /// Drive either motor directly
+    async fn motor_command(
+        &mut self,
+        motor: FireMotor,
+        command: MotorCommand,
+    ) -> Result<(), ErrorCode>
+    {
+        tracing::info!(?motor, "Received Motor Command: {:?}", command);
+
+        if self.arming.is_armed() {
+            self.arming.set(ArmState::Armed);
+        }
+
+        let operation = self.fire.motor_command(motor, command);
+
+        let outcome = self.inbox.preemptible(operation, aborts).await;
+        let faulted = self.report_faults();
+
+        match outcome {
+            // trips have already been reported above
+            Some(Err(_)) if faulted => Err(ErrorCode::MotorFault),
+            Some(Err(error)) => Err(self.fire_error(error)),
+            Some(Ok(())) => Ok(()),
+            None => {
+                tracing::info!("Motor Command {:?} aborted", command);
+                self.stop();
+                Err(ErrorCode::Superseded)
+            }
+        }
+    }
+
+    /// Fire, or spin the flywheels up or down
+    async fn fire_command(
+        &mut self,
+        command: FireCommand,
+    ) -> Result<(), ErrorCode>
+    {
+        tracing::info!("Received Fire Command: {:?}", command);
+
+        if self.arming.is_armed() {
+            self.arming.set(ArmState::Firing);
+        }
+
+        let operation = self.fire.process(command);
+        let supersedes = |next: &WebSocketMessage| {
+            // full-auto never finishes, so anything else stops it
+            matches!(command, FireCommand::Auto)
+                || matches!(next, WebSocketMessage::Fire(FireCommand::Cease))
+                || aborts(next)
+        };
+
+        let outcome = self.inbox.preemptible(operation, supersedes).await;
+        let faulted = self.report_faults();
+
+        let outcome = match outcome {
+            // trips have already been reported above
+            Some(Err(FireError::FlywheelError(_) | FireError::LoaderError(_))) if faulted => {
+                Err(ErrorCode::MotorFault)
+            }
+            Some(Err(FireError::SpinUpTimeout)) => {
+                tracing::warn!(
+                    "Fire Command {:?} failed: flywheels not up to speed",
+                    command
+                );
+                self.stop();
+                Err(ErrorCode::SpinUpTimeout)
+            }
+            Some(Err(FireError::Jammed)) => {
+                tracing::warn!("Fire Command {:?} stopped: loader jammed", command);
+                Err(ErrorCode::Jammed)
+            }
+            Some(Err(FireError::Empty)) => {
+                tracing::warn!("Fire Command {:?} refused: magazine empty", command);
+                self.cease();
+                Err(ErrorCode::Empty)
+            }
+            Some(Err(error)) => Err(self.fire_error(error)),
+            Some(Ok(())) => Ok(()),
+            None => {
+                tracing::info!("Fire Command {:?} interrupted", command);
+                self.cease();
+
+                // full-auto only ever ends by being interrupted
+                match command {
+                    FireCommand::Auto => Ok(()),
+                    _ => Err(ErrorCode::Superseded),
+                }
+            }
+        };
+
+        if self.arming.is_armed() {
+            self.arming.set(ArmState::Armed);
+        }
+
+        if let Some(event) = self.fire.take_event() {
+            publish_magazine(event);
+        }
+
+        if let Some(event) = self.fire.take_jam_event() {
+            tracing::info!(?event, "Loader jam changed");
+            EVENTS
+                .immediate_publisher()
+                .publish_immediate(WebSocketMessage::Jam(event));
+        }
+
+        outcome
+    }
+
+    /// Stop both motors, recording any driver error
+    fn stop(&mut self)
+    {
+        if let Err(error) = self.fire.stop() {
+            self.health.record_fire(error);
+        }
+    }
+
+    /// Stop firing, recording any driver error
+    fn cease(&mut self)
+    {
+        if let Err(error) = self.fire.cease() {
+            self.health.record_fire(error);
+        }
+    }
+
+    /// Record a fire control error, stopping both motors if either failed
+    ///
+    /// # Returns
+    ///
+    /// * `ErrorCode` - The error to answer the failed request with.
+    fn fire_error(
+        &mut self,
+        error: FireError<F::Error, L::Error>,
+    ) -> ErrorCode
+    {
+        let code = self.health.record_fire(error);
+
+        if let ErrorCode::ActuatorFault(_) = code {
+            self.stop();
+        }
+        code
+    }
+
+    /// Report motors cut or held back by their protection to connected clients
+    ///
+    /// Fire control is stopped after a trip, so that neither motor is left
+    /// running.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether either motor was cut.
+    fn report_faults(&mut self) -> bool
+    {
+        let mut faulted = false;
+
+        while let Some((motor, fault)) = self.fire.take_fault() {
+            tracing::warn!(?motor, ?fault, "Motor stopped by its protection");
+            EVENTS
+                .immediate_publisher()
+                .publish_immediate(WebSocketMessage::MotorFault { motor, fault });
+            faulted = true;
+        }
+
+        if faulted {
+            self.stop();
+        }
+        faulted
+    }
+
+    /// Move the turret
+    async fn servo_command(
+        &mut self,
+        command: ServoCommand,
+    ) -> Result<(), ErrorCode>
+    {
+        tracing::info!("Received Servo Command: {:?}", command);
+
+        let motion = self.servos.process(command);
+        let supersedes = |next: &WebSocketMessage| {
+            matches!(
+                next,
+                WebSocketMessage::Servo(_) | WebSocketMessage::MotorAndServo { .. }
+            ) || matches!(motor_command(next), Some(MotorCommand::Abort))
+        };
+
+        let outcome = self.inbox.preemptible(motion, supersedes).await;
+        let violation = self.servos.take_violation();
+
+        if let Some(violation) = violation {
+            tracing::warn!(
+                ?violation,
+                "Servo Command {:?} violated soft limits",
+                command
+            );
+            EVENTS
+                .immediate_publisher()
+                .publish_immediate(WebSocketMessage::LimitViolation(violation));
+        }
+
+        match outcome {
+            // rejected commands have already been reported above
+            Some(Err(_)) if violation.is_some() => Err(ErrorCode::OutOfLimits),
+            Some(Err(error)) => Err(self.health.record(Actuator::Servos, error)),
+            Some(Ok(())) => Ok(()),
+            None => {
+                tracing::info!("Servo Command {:?} superseded", command);
+                Err(ErrorCode::Superseded)
+            }
+        }
+    }
+}
+
+/// Command Router Task
+///
+/// Runs a [Router] over the board's actuators and sensors, fed by the
+/// WebSocket server through [CommandSource::global]. The magazine holds
+/// [RouterConfig::magazine] rounds, and is watched by the board's magazine
+/// switch, if it has one. The loader is watched for jams by the board's jam
+/// sensor, if it has one, and cleared as [RouterConfig::fire] says. Both
+/// motors are cut by their current sensors, where the board has them, at the
+/// limits in the config, and held to the thermal budgets it describes.
+#[cfg(feature = "board")]
+#[embassy_executor::task]
+pub async fn command_router(config: RouterConfig)
+{
+    let mcu = hardware::mcu::init_mcu();
+    let magazine = hardware::fire::Magazine::new(config.magazine).with_sensor(mcu.magazine);
+    let flywheels = mcu
+        .flywheels
+        .with_protection(mcu.flywheel_current, config.flywheel_current)
+        .with_thermal_limit(config.flywheel_heat, config.thermal);
+    let loader = mcu
+        .loader
+        .with_protection(mcu.loader_current, config.loader_current)
+        .with_thermal_limit(config.loader_heat, config.thermal);
+    let fire = FireControl::new(flywheels, loader)
+        .with_config(config.fire)
+        .with_magazine(magazine)
+        .with_jam_sensor(mcu.jam);
+
+    Router::new(fire, mcu.servos, CommandSource::global(), config)
+        .run()
+        .await
+}
+
+#[cfg(test)]
+mod tests
+{
+    use core::{cell::Cell, convert::Infallible, future::Future};
+
+    use embassy_futures::{
+        block_on,
+        select::{select, Either},
+    };
+    use embassy_time::{with_timeout, Timer};
+
+    use super::*;
+    use crate::messages::{ActuatorFault, HandlerResponse, Replies, ReplyTo};
+
+    /// A motor that records whether it is running, and can be made to fail
+    /// every time it is turned on
+    struct Switch<'a>
+    {
+        running: &'a Cell<bool>,
+        broken: bool,
+    }
+
+    impl Motor for Switch<'_>
+    {
+        type Error = ();
+
+        fn on(&mut self) -> Result<(), Self::Error>
+        {
+            match self.broken {
+                true => Err(()),
+                false => {
+                    self.running.set(true);
+                    Ok(())
+                }
+            }
+        }
+
+        fn off(&mut self) -> Result<(), Self::Error>
+        {
+            self.running.set(false);
+            Ok(())
+        }
+    }
+
+    /// Fire control over two switches, with broken flywheels if asked
+    fn launcher<'a>(
+        flywheels: &'a Cell<bool>,
+        loader: &'a Cell<bool>,
+        broken: bool,
+    ) -> FireControl<Switch<'a>, Switch<'a>>
+    {
+        FireControl::new(
+            Switch {
+                running: flywheels,
+                broken,
+            },
+            Switch {
+                running: loader,
+                broken: false,
+            },
+        )
+    }
+
+    /// A turret that takes 50 ms to reach any target
+    struct Turret<'a>
+    {
+        target: &'a Cell<Option<(u8, u8)>>,
+    }
+
+    impl Servo for Turret<'_>
+    {
+        type Error = Infallible;
+
+        async fn move_to(
+            &mut self,
+            pan: u8,
+            tilt: u8,
+        ) -> Result<(), Self::Error>
+        {
+            Timer::after_millis(50).await;
+            self.target.set(Some((pan, tilt)));
+            Ok(())
+        }
+
+        async fn process(
+            &mut self,
+            command: ServoCommand,
+        ) -> Result<(), Self::Error>
+        {
+            match command {
+                ServoCommand::PanTilt(pan, tilt) => self.move_to(pan, tilt).await,
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// A command source of its own, and two clients, for each test
+    struct Bench
+    {
+        requests: Channel<CriticalSectionRawMutex, Request, 64>,
+        estop: Signal<CriticalSectionRawMutex, Request>,
+        link: Signal<CriticalSectionRawMutex, ()>,
+        lost: Signal<CriticalSectionRawMutex, ()>,
+        client: Replies,
+        other: Replies,
+    }
+
+    impl Bench
+    {
+        const fn new() -> Self
+        {
+            Self {
+                requests: Channel::new(),
+                estop: Signal::new(),
+                link: Signal::new(),
+                lost: Signal::new(),
+                client: Replies::new(),
+                other: Replies::new(),
+            }
+        }
+
+        fn source(&'static self) -> CommandSource<'static>
+        {
+            CommandSource {
+                requests: &self.requests,
+                estop: &self.estop,
+                link: &self.link,
+                lost: &self.lost,
+            }
+        }
+
+        /// Queue a request from a client, as the WebSocket server does
+        fn send(
+            &self,
+            reply: ReplyTo,
+            id: u32,
+            message: WebSocketMessage,
+        )
+        {
+            self.link.signal(());
+            self.requests
+                .try_send(Request {
+                    id: Some(id),
+                    message,
+                    reply: Some(reply),
+                })
+                .unwrap();
+        }
+    }
+
+    /// Run the router until the script is done
+    fn run<F: Motor, L: Motor, V: Servo>(
+        router: Router<'static, F, L, (), (), V>,
+        script: impl Future<Output = ()>,
+    )
+    {
+        block_on(async {
+            match select(router.run(), script).await {
+                Either::First(never) => never,
+                Either::Second(()) => {}
+            }
+        });
+    }
+
+    /// The next message sent to a client
+    async fn next(reply: ReplyTo) -> WebSocketMessage
+    {
+        with_timeout(Duration::from_secs(1), reply.receive())
+            .await
+            .expect("no reply")
+    }
+
+    /// The next message sent to a client, which must be an answer
+    async fn answer(reply: ReplyTo) -> HandlerResponse
+    {
+        match next(reply).await {
+            WebSocketMessage::HandlerResponse(response) => response,
+            message => panic!("expected an answer, got {message:?}"),
+        }
+    }
+
+    /// Whether a client is sent nothing more for a while
+    async fn quiet(reply: ReplyTo) -> bool
+    {
+        with_timeout(Duration::from_millis(100), reply.receive())
+            .await
+            .is_err()
+    }
+
+    fn ok(id: u32) -> HandlerResponse { HandlerResponse::new(Some(id), Ok(())) }
+
+    fn error(
+        id: u32,
+        error: ErrorCode,
+    ) -> HandlerResponse
+    {
+        HandlerResponse::new(Some(id), Err(error))
+    }
+
+    #[test]
+    fn answers_go_only_to_the_requester()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+        let other = BENCH.other.open().unwrap();
+
+        run(router, async {
+            // both clients may use the same ID
+            BENCH.send(client, 1, WebSocketMessage::Arm { pin: None });
+            BENCH.send(other, 1, WebSocketMessage::Motor(MotorCommand::On));
+
+            assert_eq!(answer(client).await, ok(1));
+            assert_eq!(answer(other).await, ok(1));
+            assert!(quiet(client).await);
+            assert!(quiet(other).await);
+        });
+
+        assert!(flywheels.get());
+    }
+
+    #[test]
+    fn rejections_go_only_to_the_requester()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+        let other = BENCH.other.open().unwrap();
+
+        run(router, async {
+            BENCH.send(client, 1, WebSocketMessage::Motor(MotorCommand::On));
+
+            assert!(matches!(
+                next(client).await,
+                WebSocketMessage::Rejected(RejectReason::Disarmed)
+            ));
+            assert_eq!(answer(client).await, error(1, ErrorCode::Disarmed));
+            assert!(quiet(other).await);
+        });
+
+        assert!(!flywheels.get());
+    }
+
+    #[test]
+    fn emergency_stop_latches_until_reset()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+
+        run(router, async {
+            BENCH.send(client, 1, WebSocketMessage::Arm { pin: None });
+            BENCH.send(client, 2, WebSocketMessage::Motor(MotorCommand::On));
+            assert_eq!(answer(client).await, ok(1));
+            assert_eq!(answer(client).await, ok(2));
+            assert!(flywheels.get());
+
+            BENCH.estop.signal(Request {
+                id: Some(3),
+                message: WebSocketMessage::EStop {
+                    source: EStopSource::default(),
+                },
+                reply: Some(client),
+            });
+            assert_eq!(answer(client).await, ok(3));
+            assert!(!flywheels.get());
+
+            BENCH.send(client, 4, WebSocketMessage::Motor(MotorCommand::On));
+            assert!(matches!(
+                next(client).await,
+                WebSocketMessage::Rejected(RejectReason::EStopLatched)
+            ));
+            assert_eq!(answer(client).await, error(4, ErrorCode::EStopLatched));
+
+            // the reset leaves the launcher disarmed
+            BENCH.send(client, 5, WebSocketMessage::Reset);
+            BENCH.send(client, 6, WebSocketMessage::Motor(MotorCommand::On));
+            assert_eq!(answer(client).await, ok(5));
+            assert!(matches!(
+                next(client).await,
+                WebSocketMessage::Rejected(RejectReason::Disarmed)
+            ));
+            assert_eq!(answer(client).await, error(6, ErrorCode::Disarmed));
+        });
+
+        assert!(!flywheels.get());
+    }
+
+    #[test]
+    fn deadman_stops_the_motors()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let config = RouterConfig {
+            deadman: Duration::from_millis(100),
+            ..RouterConfig::default()
+        };
+        let router = Router::new(fire, Turret { target: &target }, BENCH.source(), config);
+        let client = BENCH.client.open().unwrap();
+
+        run(router, async {
+            BENCH.send(client, 1, WebSocketMessage::Arm { pin: None });
+            BENCH.send(client, 2, WebSocketMessage::Motor(MotorCommand::On));
+            assert_eq!(answer(client).await, ok(1));
+            assert_eq!(answer(client).await, ok(2));
+            assert!(flywheels.get());
+
+            Timer::after_millis(300).await;
+            assert!(!flywheels.get());
+
+            // and disarms the launcher
+            BENCH.send(client, 3, WebSocketMessage::Motor(MotorCommand::On));
+            assert!(matches!(
+                next(client).await,
+                WebSocketMessage::Rejected(RejectReason::Disarmed)
+            ));
+            assert_eq!(answer(client).await, error(3, ErrorCode::Disarmed));
+        });
+    }
+
+    #[test]
+    fn lost_connection_is_not_hidden_by_later_activity()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+
+        run(router, async {
+            BENCH.send(client, 1, WebSocketMessage::Arm { pin: None });
+            BENCH.send(client, 2, WebSocketMessage::Motor(MotorCommand::On));
+            assert_eq!(answer(client).await, ok(1));
+            assert_eq!(answer(client).await, ok(2));
+            assert!(flywheels.get());
+
+            // both raised before the router gets to run
+            BENCH.lost.signal(());
+            BENCH.link.signal(());
+
+            // well within the deadman timeout
+            Timer::after_millis(100).await;
+        });
+
+        assert!(!flywheels.get());
+    }
+
+    #[test]
+    fn failing_actuator_is_faulted_until_reset()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, true);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+        let other = BENCH.other.open().unwrap();
+        let faulted = ErrorCode::ActuatorFault(Actuator::Flywheels);
+
+        run(router, async {
+            BENCH.send(client, 1, WebSocketMessage::Arm { pin: None });
+            BENCH.send(client, 2, WebSocketMessage::Motor(MotorCommand::On));
+            assert_eq!(answer(client).await, ok(1));
+            assert!(matches!(
+                next(client).await,
+                WebSocketMessage::ActuatorFault(ActuatorFault {
+                    actuator: Actuator::Flywheels,
+                    errors: 1,
+                })
+            ));
+            assert_eq!(answer(client).await, error(2, faulted));
+
+            BENCH.send(client, 3, WebSocketMessage::Motor(MotorCommand::On));
+            assert!(matches!(
+                next(client).await,
+                WebSocketMessage::Rejected(RejectReason::Faulted(Actuator::Flywheels))
+            ));
+            assert_eq!(answer(client).await, error(3, faulted));
+
+            // the loader still works
+            BENCH.send(
+                client,
+                4,
+                WebSocketMessage::DriveMotor {
+                    motor: FireMotor::Loader,
+                    command: MotorCommand::On,
+                },
+            );
+            assert_eq!(answer(client).await, ok(4));
+            assert!(loader.get());
+            assert!(quiet(other).await);
+        });
+    }
+
+    #[test]
+    fn aim_and_stop_is_accepted_while_disarmed()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+
+        run(router, async {
+            BENCH.send(
+                client,
+                1,
+                WebSocketMessage::MotorAndServo {
+                    target: FireMotor::Flywheels,
+                    motor: MotorCommand::Off,
+                    servo: ServoCommand::PanTilt(30, 45),
+                },
+            );
+
+            assert_eq!(answer(client).await, ok(1));
+            assert!(quiet(client).await);
+        });
+
+        assert_eq!(target.get(), Some((30, 45)));
+        assert!(!flywheels.get());
+    }
+
+    #[test]
+    fn newer_servo_command_supersedes_a_move()
+    {
+        static BENCH: Bench = Bench::new();
+        let (flywheels, loader, target) = (Cell::new(false), Cell::new(false), Cell::new(None));
+        let fire = launcher(&flywheels, &loader, false);
+        let router = Router::new(
+            fire,
+            Turret { target: &target },
+            BENCH.source(),
+            RouterConfig::default(),
+        );
+        let client = BENCH.client.open().unwrap();
+
+        run(router, async {
+            BENCH.send(
+                client,
+                1,
+                WebSocketMessage::Servo(ServoCommand::PanTilt(10, 10)),
+            );
+            Timer::after_millis(10).await;
+            BENCH.send(
+                client,
+                2,
+                WebSocketMessage::Servo(ServoCommand::PanTilt(20, 20)),
+            );
+
+            assert_eq!(answer(client).await, error(1, ErrorCode::Superseded));
+            assert_eq!(answer(client).await, ok(2));
+        });
+
+        assert_eq!(target.get(), Some((20, 20)));
+    }
+}
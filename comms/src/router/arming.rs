@@ -0,0 +1,90 @@
+//! ## Arming
+//!
+//! Keeps the launcher safe until it is deliberately armed, and disarms it
+//! again once it has been left idle.
+
+use core::future::pending;
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::messages::{ArmState, WebSocketMessage, EVENTS};
+
+/// Arming
+///
+/// Tracks the arm state, and when the launcher disarms itself.
+///
+/// # Fields
+/// - `state`: The current arm state.
+/// - `pin`: The PIN needed to arm, if any.
+/// - `timeout`: How long the launcher stays armed while idle.
+/// - `expires`: When the launcher disarms itself, while armed.
+pub(crate) struct Arming
+{
+    state: ArmState,
+    pin: Option<u32>,
+    timeout: Duration,
+    expires: Option<Instant>,
+}
+
+impl Arming
+{
+    /// Create a new, disarmed `Arming`
+    pub(crate) const fn new(
+        pin: Option<u32>,
+        timeout: Duration,
+    ) -> Self
+    {
+        Self {
+            state: ArmState::Disarmed,
+            pin,
+            timeout,
+            expires: None,
+        }
+    }
+
+    /// The current arm state
+    pub(crate) const fn state(&self) -> ArmState { self.state }
+
+    /// Whether motor and fire commands are accepted
+    pub(crate) fn is_armed(&self) -> bool { self.state != ArmState::Disarmed }
+
+    /// Whether an `Arm` message carrying the supplied PIN may arm
+    pub(crate) fn accepts(
+        &self,
+        pin: Option<u32>,
+    ) -> bool
+    {
+        self.pin.is_none() || self.pin == pin
+    }
+
+    /// Move to a new state, telling clients if it changed
+    ///
+    /// Restarts the idle timeout while armed.
+    pub(crate) fn set(
+        &mut self,
+        state: ArmState,
+    )
+    {
+        self.expires = match state {
+            ArmState::Disarmed => None,
+            ArmState::Armed | ArmState::Firing => Some(Instant::now() + self.timeout),
+        };
+
+        if self.state != state {
+            tracing::info!(?state, "Arm state changed");
+            self.state = state;
+            EVENTS
+                .immediate_publisher()
+                .publish_immediate(WebSocketMessage::ArmState(state));
+        }
+    }
+
+    /// Wait until the launcher has been armed and idle for the timeout
+    pub(crate) async fn expired(&self)
+    {
+        match self.expires {
+            Some(expires) => Timer::at(expires).await,
+            None => pending().await,
+        }
+    }
+}
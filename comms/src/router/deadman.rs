@@ -0,0 +1,72 @@
+//! ## Deadman
+//!
+//! Stops the robot when the controller goes silent or disconnects.
+
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+
+/// Deadman
+///
+/// Trips when the controller has been silent for longer than the timeout,
+/// or as soon as its WebSocket closes. The timeout only runs once a client
+/// has been heard from, and stops again when the deadman trips, so nothing
+/// trips while no client is connected. A lost connection is always seen,
+/// even when activity is signalled after it, since it is signalled apart.
+///
+/// # Fields
+/// - `link`: Where the controller's activity is signalled.
+/// - `lost`: Where the loss of the controller's connection is signalled.
+/// - `timeout`: How long the controller may stay silent.
+/// - `deadline`: When the deadman trips, unless a client is heard from first.
+pub(crate) struct Deadman<'a>
+{
+    link: &'a Signal<CriticalSectionRawMutex, ()>,
+    lost: &'a Signal<CriticalSectionRawMutex, ()>,
+    timeout: Duration,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Deadman<'a>
+{
+    /// Create a new `Deadman`, not yet running
+    pub(crate) fn new(
+        link: &'a Signal<CriticalSectionRawMutex, ()>,
+        lost: &'a Signal<CriticalSectionRawMutex, ()>,
+        timeout: Duration,
+    ) -> Self
+    {
+        Self {
+            link,
+            lost,
+            timeout,
+            deadline: None,
+        }
+    }
+
+    /// Wait until the deadman trips
+    pub(crate) async fn tripped(&mut self)
+    {
+        loop {
+            // a loss is checked first, so activity after it can't hide it
+            let alive = match self.deadline {
+                Some(deadline) => matches!(
+                    select3(self.lost.wait(), self.link.wait(), Timer::at(deadline)).await,
+                    Either3::Second(())
+                ),
+                None => matches!(
+                    select(self.lost.wait(), self.link.wait()).await,
+                    Either::Second(())
+                ),
+            };
+
+            match alive {
+                true => self.deadline = Some(Instant::now() + self.timeout),
+                false => {
+                    self.deadline = None;
+                    return;
+                }
+            }
+        }
+    }
+}
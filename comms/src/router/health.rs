@@ -0,0 +1,126 @@
+//! ## Actuator Health
+//!
+//! Keeps track of actuator driver errors, so that a failing actuator is
+//! reported once and then left alone until it is reset.
+
+use core::fmt;
+
+use hardware::fire::FireError;
+
+use super::drives;
+use crate::messages::{Actuator, ActuatorFault, ErrorCode, Request, WebSocketMessage};
+
+/// Actuator Health
+///
+/// Counts the driver errors from each [Actuator], and which of them are
+/// faulted.
+///
+/// # Fields
+/// - `errors`: How many driver errors each actuator has had.
+/// - `faulted`: Which actuators are faulted, until a `Reset`.
+/// - `unreported`: Faults not yet reported to the client that caused them.
+#[derive(Default)]
+pub(crate) struct Health
+{
+    errors: [u32; 3],
+    faulted: [bool; 3],
+    unreported: [Option<ActuatorFault>; 3],
+}
+
+impl Health
+{
+    /// Record a driver error
+    ///
+    /// Every error is logged and counted, but the fault is only reported
+    /// when the actuator becomes faulted, rather than for each error while it
+    /// stays faulted.
+    ///
+    /// # Returns
+    ///
+    /// * `ErrorCode` - The error to answer the failed request with.
+    pub(crate) fn record(
+        &mut self,
+        actuator: Actuator,
+        error: impl fmt::Debug,
+    ) -> ErrorCode
+    {
+        let index = actuator as usize;
+        let errors = self.errors[index].saturating_add(1);
+        self.errors[index] = errors;
+        tracing::error!(?actuator, ?error, errors, "Actuator failed");
+
+        if !self.faulted[index] {
+            self.faulted[index] = true;
+            self.unreported[index] = Some(ActuatorFault { actuator, errors });
+        }
+        ErrorCode::ActuatorFault(actuator)
+    }
+
+    /// Record a fire control error, if it came from either motor's driver
+    ///
+    /// # Returns
+    ///
+    /// * `ErrorCode` - The error to answer the failed request with.
+    pub(crate) fn record_fire<F: fmt::Debug, L: fmt::Debug>(
+        &mut self,
+        error: FireError<F, L>,
+    ) -> ErrorCode
+    {
+        match error {
+            FireError::FlywheelError(error) => self.record(Actuator::Flywheels, error),
+            FireError::LoaderError(error) => self.record(Actuator::Loader, error),
+            FireError::SpinUpTimeout => ErrorCode::SpinUpTimeout,
+            FireError::Empty => ErrorCode::Empty,
+            FireError::Jammed => ErrorCode::Jammed,
+        }
+    }
+
+    /// Report new faults to the client whose request caused them
+    ///
+    /// Faults with no request to blame, such as those hit while stopping the
+    /// motors after the controller is lost, are only logged, and left to the
+    /// `Faults` telemetry topic.
+    pub(crate) fn report(
+        &mut self,
+        request: Option<&Request>,
+    )
+    {
+        for fault in self.unreported.iter_mut().filter_map(Option::take) {
+            if let Some(request) = request {
+                request.notify(WebSocketMessage::ActuatorFault(fault));
+            }
+        }
+    }
+
+    /// Whether the actuator is faulted
+    pub(crate) fn is_faulted(
+        &self,
+        actuator: Actuator,
+    ) -> bool
+    {
+        self.faulted[actuator as usize]
+    }
+
+    /// The first faulted actuator the message drives, if any
+    pub(crate) fn blocks(
+        &self,
+        message: &WebSocketMessage,
+    ) -> Option<Actuator>
+    {
+        Actuator::ALL
+            .into_iter()
+            .find(|&actuator| self.is_faulted(actuator) && drives(message, actuator))
+    }
+
+    /// Clear every fault, keeping the error counts
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether any actuator was faulted.
+    pub(crate) fn reset(&mut self) -> bool
+    {
+        let faulted = self.faulted.contains(&true);
+        self.faulted = [false; 3];
+        faulted
+    }
+}
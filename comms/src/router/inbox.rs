@@ -0,0 +1,172 @@
+//! ## Inbox
+//!
+//! Where the router receives its requests, keeping emergency stops and the
+//! deadman ahead of everything else, and holding on to requests that arrive
+//! while an operation runs.
+
+use core::{future::Future, pin::pin};
+
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    channel::{Channel, TrySendError},
+    signal::Signal,
+};
+use embassy_time::Duration;
+
+use super::{deadman::Deadman, reject, CommandSource};
+use crate::messages::{ErrorCode, RejectReason, Request, WebSocketMessage};
+
+/// Requests that can wait behind a running operation
+const BACKLOG: usize = 16;
+
+/// Inbox
+///
+/// The router's side of a [CommandSource], along with its deadman.
+///
+/// # Fields
+/// - `requests`: Requests handled in order.
+/// - `estop`: Emergency stops, handled ahead of any queued requests.
+/// - `deadman`: Trips when the controller goes silent or disconnects.
+/// - `urgent`: An emergency stop, or the deadman tripping, that cancelled an
+///   operation, handled ahead of the backlog.
+/// - `backlog`: Requests received while an operation ran, handled in order once
+///   it is done.
+pub(crate) struct Inbox<'a>
+{
+    requests: &'a Channel<CriticalSectionRawMutex, Request, 64>,
+    estop: &'a Signal<CriticalSectionRawMutex, Request>,
+    deadman: Deadman<'a>,
+    urgent: Option<Request>,
+    backlog: Channel<NoopRawMutex, Request, BACKLOG>,
+}
+
+impl<'a> Inbox<'a>
+{
+    /// Create a new `Inbox` from the supplied source
+    pub(crate) fn new(
+        source: CommandSource<'a>,
+        deadman: Duration,
+    ) -> Self
+    {
+        Self {
+            requests: source.requests,
+            estop: source.estop,
+            deadman: Deadman::new(source.link, source.lost, deadman),
+            urgent: None,
+            backlog: Channel::new(),
+        }
+    }
+
+    /// Wait for the next request
+    ///
+    /// A raised emergency stop, or the deadman tripping as `ConnectionLost`,
+    /// is returned ahead of any queued request, and requests received while
+    /// an operation ran are returned ahead of newer ones.
+    pub(crate) async fn receive(&mut self) -> Request
+    {
+        if let Some(request) = self.urgent.take().or_else(|| self.estop.try_take()) {
+            return request;
+        }
+
+        if let Ok(request) = self.backlog.try_receive() {
+            return request;
+        }
+
+        self.wait().await
+    }
+
+    /// Wait for an emergency stop, the deadman or a newly queued request
+    async fn wait(&mut self) -> Request
+    {
+        match select3(
+            self.estop.wait(),
+            self.deadman.tripped(),
+            self.requests.receive(),
+        )
+        .await
+        {
+            Either3::First(request) => request,
+            Either3::Second(()) => Request {
+                id: None,
+                message: WebSocketMessage::ConnectionLost,
+                reply: None,
+            },
+            Either3::Third(request) => request,
+        }
+    }
+
+    /// Hold on to a request until the running operation is done
+    ///
+    /// The request is rejected with [RejectReason::QueueFull] if the backlog
+    /// is full.
+    fn defer(
+        &self,
+        request: Request,
+    )
+    {
+        if let Err(TrySendError::Full(request)) = self.backlog.try_send(request) {
+            reject(request, RejectReason::QueueFull);
+        }
+    }
+
+    /// Drop every waiting request, telling clients the controller was lost
+    pub(crate) fn drain(&self)
+    {
+        while let Ok(request) = self
+            .backlog
+            .try_receive()
+            .or_else(|_| self.requests.try_receive())
+        {
+            tracing::warn!("Dropped {:?}: controller lost", request.message);
+            request.respond(Err(ErrorCode::ControllerLost));
+        }
+    }
+
+    /// Preemptible Operation
+    ///
+    /// Runs a long-running actuator operation until it completes, or until a
+    /// newer request that `supersedes` it arrives, in which case the
+    /// operation is cancelled by dropping it.
+    ///
+    /// Requests keep being received while the operation runs, and wait in
+    /// the backlog so the router can handle them next, in the order they
+    /// were received. A request that does not supersede the operation simply
+    /// waits for it to finish, without stopping the router from noticing a
+    /// later one that does. An emergency stop, or the deadman tripping,
+    /// cancels the operation either way, and is handled ahead of the backlog.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<F::Output>` - The output of the operation, or `None` if it was
+    ///   cancelled.
+    pub(crate) async fn preemptible<F: Future>(
+        &mut self,
+        operation: F,
+        supersedes: impl Fn(&WebSocketMessage) -> bool,
+    ) -> Option<F::Output>
+    {
+        let mut operation = pin!(operation);
+
+        loop {
+            let request = match select(&mut operation, self.wait()).await {
+                Either::First(output) => return Some(output),
+                Either::Second(request) => request,
+            };
+
+            if let WebSocketMessage::EStop { .. } | WebSocketMessage::ConnectionLost =
+                request.message
+            {
+                self.urgent = Some(request);
+                return None;
+            }
+
+            let superseded = supersedes(&request.message);
+            self.defer(request);
+
+            if superseded {
+                return None;
+            }
+        }
+    }
+}
@@ -5,7 +5,11 @@
 //! `picoserve` and `embassy` crates to manage network operations
 //! and timing.
 
+use core::{cell::Cell, future::pending};
+
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_net::{driver::Driver as NetworkDriver, Stack};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex, pubsub::DynSubscriber};
 use embassy_time::Duration;
 use picoserve::{
     io::embedded_io_async as embedded_aio,
@@ -20,7 +24,13 @@ use picoserve::{
     Router,
 };
 
-use crate::messages;
+use crate::{
+    messages::{self, Replies, ReplyTo, WebSocketMessage},
+    telemetry::{self, Topics},
+};
+
+/// Replies for each open WebSocket, one claimed by each connection
+static REPLIES: [Replies; 4] = [const { Replies::new() }; 4];
 
 /// Runs the comms with the given configuration.
 ///
@@ -108,7 +118,9 @@ impl picoserve::Timer for ServerTimer
 ///
 /// This struct handles WebSocket connections, processing incoming messages
 /// and responding appropriately. It uses the `picoserve` crate's WebSocket
-/// callback mechanism to handle messages.
+/// callback mechanism to handle messages, and forwards every message
+/// published on [messages::EVENTS] to the client, along with the answers to
+/// its own requests and the telemetry topics it has subscribed to.
 pub struct WebSocket;
 
 impl WebSocketCallback for WebSocket
@@ -116,8 +128,15 @@ impl WebSocketCallback for WebSocket
     /// Runs the WebSocket connection, processing incoming messages.
     ///
     /// This method is called when a WebSocket connection is established.
-    /// It reads messages from the client and processes them, sending
-    /// responses as needed.
+    /// It reads requests from the client and hands them to the router, which
+    /// answers them through the connection's [Replies] once they have been
+    /// handled. Frames that can't be parsed, and requests that need no
+    /// routing, are answered straight away. Events from the router and
+    /// those answers are forwarded to the client concurrently, sharing the
+    /// socket's writer with the reader.
+    /// When the controlling connection ends, for whatever reason, or when no
+    /// other connection is left, the router's deadman is tripped so that every
+    /// actuator is stopped.
     ///
     /// # Parameters
     ///
@@ -131,60 +150,170 @@ impl WebSocketCallback for WebSocket
     async fn run<Reader, Writer>(
         self,
         mut rx: SocketRx<Reader>,
-        mut tx: SocketTx<Writer>,
+        tx: SocketTx<Writer>,
     ) -> Result<(), Writer::Error>
     where
         Reader: embedded_aio::Read,
         Writer: embedded_aio::Write<Error = Reader::Error>,
     {
         let mut buffer = [0; 1024];
+        let tx = Mutex::<NoopRawMutex, _>::new(tx);
+        let topics = Cell::new(Topics::default());
+        let connection = telemetry::Connection::open();
 
-        let close_reason = loop {
-            match rx.next_message(&mut buffer).await {
-                Ok(Message::Pong(_)) => continue,
-                Ok(Message::Ping(data)) => tx.send_pong(data).await?,
-                Ok(Message::Close(reason)) => {
-                    tracing::info!(?reason, "websocket closed");
-                    break None;
-                }
-                Ok(Message::Text(data)) => {
-                    match serde_json::from_str::<messages::WebSocketMessage>(data) {
-                        Ok(message) => {
-                            messages::CHANNEL.send(message).await;
-                            tx.send_text(data).await?
+        let reply_to = REPLIES.iter().find_map(Replies::open);
+        if reply_to.is_none() {
+            tracing::warn!("too many websocket clients, requests will not be answered");
+        }
+
+        let mut events = messages::EVENTS.dyn_subscriber().ok();
+        if events.is_none() {
+            tracing::warn!("too many websocket clients, events will not be forwarded");
+        }
+
+        let mut frames = telemetry::TELEMETRY.dyn_subscriber().ok();
+        if frames.is_none() {
+            tracing::warn!("too many websocket clients, telemetry will not be forwarded");
+        }
+
+        let forwarder = async {
+            loop {
+                let event =
+                    match select3(next(&mut events), next(&mut frames), reply(reply_to)).await {
+                        Either3::First(event) | Either3::Third(event) => event,
+                        Either3::Second(frame) if topics.get().contains(frame.topic()) => {
+                            WebSocketMessage::Telemetry(frame)
                         }
-                        Err(error) => {
-                            tracing::error!(?error, "error deserializing incoming message")
+                        Either3::Second(_) => continue,
+                    };
+
+                match serde_json::to_string(&event) {
+                    Ok(text) => {
+                        if let Err(error) = tx.lock().await.send_text(&text).await {
+                            break error;
                         }
                     }
+                    Err(error) => tracing::error!(?error, "error serializing outgoing message"),
                 }
-                Ok(Message::Binary(data)) => match serde_json::from_slice::<
-                    messages::WebSocketMessage,
-                >(data)
-                {
-                    Ok(message) => {
-                        messages::CHANNEL.send(message).await;
-                        tx.send_binary(data).await?
+            }
+        };
+
+        let reader = async {
+            let close_reason = loop {
+                match rx.next_message(&mut buffer).await {
+                    Ok(Message::Pong(_)) => continue,
+                    Ok(Message::Ping(data)) => tx.lock().await.send_pong(data).await?,
+                    Ok(Message::Close(reason)) => {
+                        tracing::info!(?reason, "websocket closed");
+                        break None;
                     }
-                    Err(error) => tracing::error!(?error, "error deserializing incoming message"),
-                },
-                Err(error) => {
-                    tracing::error!(?error, "websocket error");
-
-                    let code = match error {
-                        ReadMessageError::TextIsNotUtf8 => 1007,
-                        ReadMessageError::ReservedOpcode(_) => 1003,
-                        ReadMessageError::ReadFrameError(_)
-                        | ReadMessageError::UnexpectedMessageStart
-                        | ReadMessageError::MessageStartsWithContinuation => 1002,
-                        ReadMessageError::Io(err) => return Err(err),
-                    };
+                    Ok(Message::Text(data)) => {
+                        handle_frame(data.as_bytes(), &topics, reply_to, &tx).await?
+                    }
+                    Ok(Message::Binary(data)) => handle_frame(data, &topics, reply_to, &tx).await?,
+                    Err(error) => {
+                        tracing::error!(?error, "websocket error");
 
-                    break Some((code, "Websocket Error"));
-                }
+                        let code = match error {
+                            ReadMessageError::TextIsNotUtf8 => 1007,
+                            ReadMessageError::ReservedOpcode(_) => 1003,
+                            ReadMessageError::ReadFrameError(_)
+                            | ReadMessageError::UnexpectedMessageStart
+                            | ReadMessageError::MessageStartsWithContinuation => 1002,
+                            ReadMessageError::Io(err) => return Err(err),
+                        };
+
+                        break Some((code, "Websocket Error"));
+                    }
+                };
             };
+
+            Ok(close_reason)
+        };
+
+        let outcome = select(reader, forwarder).await;
+
+        if let Some(reply_to) = reply_to {
+            reply_to.close();
+        }
+
+        // however the connection ended, if it was the controller, it is gone
+        messages::connection_lost(reply_to, connection.close());
+
+        let close_reason = match outcome {
+            Either::First(close_reason) => close_reason?,
+            Either::Second(error) => return Err(error),
         };
 
-        tx.close(close_reason).await
+        tx.into_inner().close(close_reason).await
+    }
+}
+
+/// Handle a single text or binary frame from a client
+///
+/// The frame is parsed as a [messages::Request] and handed to the router,
+/// after any change of telemetry subscription is applied to `topics`. A frame
+/// that can't be parsed is answered on this socket with a `Malformed`
+/// response, as are requests the router doesn't need to see, or has no room
+/// for.
+///
+/// # Parameters
+///
+/// - `data`: The contents of the frame.
+/// - `topics`: The telemetry topics the client has subscribed to.
+/// - `reply_to`: Where the router answers this client's requests.
+/// - `tx`: The socket for sending messages, shared with the event forwarder.
+///
+/// # Returns
+///
+/// A result indicating whether a response could be sent, if one was needed.
+async fn handle_frame<Writer: embedded_aio::Write>(
+    data: &[u8],
+    topics: &Cell<Topics>,
+    reply_to: Option<ReplyTo>,
+    tx: &Mutex<NoopRawMutex, SocketTx<Writer>>,
+) -> Result<(), Writer::Error>
+{
+    let response = match messages::Request::parse(data) {
+        Ok(mut request) => {
+            match request.message {
+                WebSocketMessage::Subscribe(topic) => topics.set(topics.get().with(topic)),
+                WebSocketMessage::Unsubscribe(topic) => topics.set(topics.get().without(topic)),
+                _ => {}
+            }
+
+            request.reply = reply_to;
+            match messages::dispatch(request) {
+                Some(response) => WebSocketMessage::HandlerResponse(response),
+                None => return Ok(()),
+            }
+        }
+        Err(response) => WebSocketMessage::HandlerResponse(response),
+    };
+
+    match serde_json::to_string(&response) {
+        Ok(text) => tx.lock().await.send_text(&text).await,
+        Err(error) => {
+            tracing::error!(?error, "error serializing outgoing message");
+            Ok(())
+        }
+    }
+}
+
+/// Wait for the next answer to send back, or forever without any replies
+async fn reply(reply_to: Option<ReplyTo>) -> WebSocketMessage
+{
+    match reply_to {
+        Some(reply_to) => reply_to.receive().await,
+        None => pending().await,
+    }
+}
+
+/// Wait for the next message from a subscription, or forever without one
+async fn next<T: Clone>(subscriber: &mut Option<DynSubscriber<'_, T>>) -> T
+{
+    match subscriber {
+        Some(subscriber) => subscriber.next_message_pure().await,
+        None => pending().await,
     }
 }
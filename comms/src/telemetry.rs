@@ -0,0 +1,342 @@
+//! ## Telemetry Module
+//!
+//! Periodically pushes the state of the robot to every connected WebSocket.
+//! The router shares its state through [STATE], and [publish] sends it on to
+//! clients as [Telemetry] frames, one per [Topic], along with the uptime and
+//! network status. Each client only receives the topics it has subscribed to.
+
+use core::cell::Cell;
+
+use embassy_net::{driver::Driver as NetworkDriver, Stack};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    pubsub::PubSubChannel,
+    watch::Watch,
+};
+use embassy_time::{Duration, Instant, Ticker};
+use hardware::{
+    fire::{JamSensor, MagazineSensor},
+    FireControl,
+    Motor,
+};
+
+use crate::messages::{Actuator, ArmState};
+
+/// Default Telemetry Period
+///
+/// How often telemetry is sampled and pushed to clients.
+pub const TELEMETRY_PERIOD: Duration = Duration::from_millis(250);
+
+/// Global Robot State
+///
+/// The most recent [RobotState], sent by the router and read by [publish].
+pub static STATE: Watch<CriticalSectionRawMutex, RobotState, 1> = Watch::new();
+
+/// Global Channel for Telemetry
+///
+/// This static publish/subscribe channel carries [Telemetry] frames from
+/// [publish] to the clients. Every open WebSocket subscribes to it, and
+/// forwards the frames for the topics its client subscribed to. It holds up to
+/// 10 frames for up to 4 subscribers; when full, the oldest frame is dropped.
+pub static TELEMETRY: PubSubChannel<CriticalSectionRawMutex, Telemetry, 10, 4, 1> =
+    PubSubChannel::new();
+
+/// Connected WebSocket clients
+static CLIENTS: Mutex<CriticalSectionRawMutex, Cell<u8>> = Mutex::new(Cell::new(0));
+
+/// Telemetry Topic
+///
+/// Variants:
+/// - `Turret`: The pan and tilt angles.
+///   - Ex: `{ "Subscribe": "Turret" }`
+/// - `Motors`: Whether the flywheels are running, and what each motor's sensors
+///   read.
+/// - `Arming`: The arm state.
+/// - `Faults`: Conditions that stop the launcher from firing.
+/// - `System`: The uptime and network status.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Topic
+{
+    Turret,
+    Motors,
+    Arming,
+    Faults,
+    System,
+}
+
+impl Topic
+{
+    /// The topic's bit in a [Topics] set
+    const fn bit(self) -> u8 { 1 << self as u8 }
+}
+
+/// Topic Set
+///
+/// The telemetry topics a client has subscribed to. Empty by default, so
+/// clients only receive telemetry once they ask for it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topics(u8);
+
+impl Topics
+{
+    /// Whether the set includes the supplied topic
+    pub const fn contains(
+        self,
+        topic: Topic,
+    ) -> bool
+    {
+        self.0 & topic.bit() != 0
+    }
+
+    /// The set, with the supplied topic added
+    #[must_use]
+    pub const fn with(
+        self,
+        topic: Topic,
+    ) -> Self
+    {
+        Self(self.0 | topic.bit())
+    }
+
+    /// The set, with the supplied topic removed
+    #[must_use]
+    pub const fn without(
+        self,
+        topic: Topic,
+    ) -> Self
+    {
+        Self(self.0 & !topic.bit())
+    }
+}
+
+/// Motor State
+///
+/// What a motor's sensors read, where it has them.
+///
+/// # Fields
+/// - `rpm`: The measured speed.
+/// - `current`: The measured current, in amps.
+/// - `thermal_budget`: The share of the motor's thermal budget left.
+#[derive(Copy, Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MotorState
+{
+    pub rpm: Option<f32>,
+    pub current: Option<f32>,
+    pub thermal_budget: Option<f32>,
+}
+
+impl MotorState
+{
+    /// Read the state of the supplied motor
+    pub fn of<M: Motor>(motor: &M) -> Self
+    {
+        Self {
+            rpm: motor.rpm(),
+            current: motor.current(),
+            thermal_budget: motor.thermal_budget(),
+        }
+    }
+}
+
+/// Faults
+///
+/// # Fields
+/// - `estop`: An emergency stop is latched.
+/// - `jammed`: A loader jam is waiting to be acknowledged.
+/// - `empty`: The magazine is empty.
+/// - `flywheels`: The flywheel motor's driver failed, until a `Reset`.
+/// - `loader`: The loader motor's driver failed, until a `Reset`.
+/// - `servos`: The servos' driver failed, until a `Reset`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Faults
+{
+    pub estop: bool,
+    pub jammed: bool,
+    pub empty: bool,
+    pub flywheels: bool,
+    pub loader: bool,
+    pub servos: bool,
+}
+
+/// Robot State
+///
+/// Everything the router knows about the robot, as of the last sample.
+///
+/// # Fields
+/// - `position`: The pan and tilt angles, once the servos have been moved.
+/// - `spinning`: Whether the flywheels are running.
+/// - `flywheels`: The flywheel motor's state.
+/// - `loader`: The loader motor's state.
+/// - `arm`: The arm state.
+/// - `faults`: Conditions that stop the launcher from firing.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RobotState
+{
+    pub position: Option<(f32, f32)>,
+    pub spinning: bool,
+    pub flywheels: MotorState,
+    pub loader: MotorState,
+    pub arm: ArmState,
+    pub faults: Faults,
+}
+
+impl RobotState
+{
+    /// Sample the state of the robot
+    pub fn sample<F: Motor, L: Motor, S: MagazineSensor, J: JamSensor>(
+        position: Option<(f32, f32)>,
+        fire: &FireControl<F, L, S, J>,
+        arm: ArmState,
+        estop: bool,
+        faulted: impl Fn(Actuator) -> bool,
+    ) -> Self
+    {
+        Self {
+            position,
+            spinning: fire.is_spinning(),
+            flywheels: MotorState::of(fire.flywheels()),
+            loader: MotorState::of(fire.loader()),
+            arm,
+            faults: Faults {
+                estop,
+                jammed: fire.is_jammed(),
+                empty: fire.magazine().is_some_and(|magazine| magazine.is_empty()),
+                flywheels: faulted(Actuator::Flywheels),
+                loader: faulted(Actuator::Loader),
+                servos: faulted(Actuator::Servos),
+            },
+        }
+    }
+}
+
+/// Telemetry
+///
+/// A single telemetry frame, sent to clients subscribed to its topic.
+///
+/// Variants:
+/// - `Turret { pan, tilt }`: The pan and tilt angles, in degrees.
+///   - Ex: `{ "Telemetry": { "Turret": { "pan": 90.0, "tilt": 45.0 } } }`
+/// - `Motors { spinning, flywheels, loader }`: Whether the flywheels are
+///   running, and what each motor's sensors read.
+/// - `Arming(ArmState)`: The arm state.
+///   - Ex: `{ "Telemetry": { "Arming": "Armed" } }`
+/// - `Faults(Faults)`: Conditions that stop the launcher from firing.
+/// - `System { uptime, link_up, address, clients }`: Seconds since boot,
+///   whether the network link is up, the IPv4 address once configured, and the
+///   number of connected clients.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Telemetry
+{
+    Turret
+    {
+        pan: Option<f32>,
+        tilt: Option<f32>,
+    },
+    Motors
+    {
+        spinning: bool,
+        flywheels: MotorState,
+        loader: MotorState,
+    },
+    Arming(ArmState),
+    Faults(Faults),
+    System
+    {
+        uptime: u64,
+        link_up: bool,
+        address: Option<[u8; 4]>,
+        clients: u8,
+    },
+}
+
+impl Telemetry
+{
+    /// The topic the frame belongs to
+    pub const fn topic(&self) -> Topic
+    {
+        match self {
+            Self::Turret { .. } => Topic::Turret,
+            Self::Motors { .. } => Topic::Motors,
+            Self::Arming(_) => Topic::Arming,
+            Self::Faults(_) => Topic::Faults,
+            Self::System { .. } => Topic::System,
+        }
+    }
+}
+
+/// Connection
+///
+/// Counts a WebSocket client as connected for as long as it is held.
+pub struct Connection(());
+
+impl Connection
+{
+    /// Count a newly connected client
+    pub fn open() -> Self
+    {
+        CLIENTS.lock(|clients| clients.set(clients.get().saturating_add(1)));
+        Self(())
+    }
+
+    /// Count the client as disconnected
+    ///
+    /// # Returns
+    ///
+    /// * `u8` - How many clients are still connected.
+    pub fn close(self) -> u8
+    {
+        drop(self);
+        CLIENTS.lock(Cell::get)
+    }
+}
+
+impl Drop for Connection
+{
+    fn drop(&mut self) { CLIENTS.lock(|clients| clients.set(clients.get().saturating_sub(1))); }
+}
+
+/// Runs the telemetry publisher.
+///
+/// Pushes every topic to [TELEMETRY] once each `period`, from the most recent
+/// [RobotState] and the status of the network stack.
+///
+/// # Parameters
+///
+/// - `stack`: A reference to the network stack.
+/// - `period`: How often telemetry is pushed.
+pub async fn publish<Driver: NetworkDriver>(
+    stack: &Stack<Driver>,
+    period: Duration,
+) -> !
+{
+    let publisher = TELEMETRY.immediate_publisher();
+    let mut ticker = Ticker::every(period);
+
+    loop {
+        ticker.next().await;
+
+        let state = STATE.try_get().unwrap_or_default();
+        let (pan, tilt) = state.position.unzip();
+
+        let frames = [
+            Telemetry::Turret { pan, tilt },
+            Telemetry::Motors {
+                spinning: state.spinning,
+                flywheels: state.flywheels,
+                loader: state.loader,
+            },
+            Telemetry::Arming(state.arm),
+            Telemetry::Faults(state.faults),
+            Telemetry::System {
+                uptime: Instant::now().as_secs(),
+                link_up: stack.is_link_up(),
+                address: stack.config_v4().map(|config| config.address.address().0),
+                clients: CLIENTS.lock(Cell::get),
+            },
+        ];
+
+        for frame in frames {
+            publisher.publish_immediate(frame);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! ## Emergency Stop Module
+//!
+//! Sources of an emergency stop, and a GPIO input for a physical e-stop
+//! button.
+
+use core::fmt;
+
+use embassy_time::{Duration, Ticker};
+use embedded_hal::digital::InputPin;
+
+/// E-Stop Source
+///
+/// What triggered an emergency stop.
+///
+/// Variants:
+/// - `Client`: A client sent an `EStop` message.
+/// - `Input`: The e-stop input was triggered, such as a physical button.
+#[derive(Copy, Clone, fmt::Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum EStopSource
+{
+    #[default]
+    Client,
+    Input,
+}
+
+/// E-Stop Input
+///
+/// An e-stop button or switch on a single input pin, polled for presses.
+///
+/// # Type Parameters
+/// - `P`: The input pin the button is connected to.
+///
+/// # Fields
+/// - `pin`: The input pin the button is connected to.
+/// - `active_low`: Whether the button pulls the pin low when pressed, as with a
+///   button to ground and a pull-up.
+/// - `poll`: How often the pin is read.
+pub struct EStopInput<P: InputPin>
+{
+    pin: P,
+    active_low: bool,
+    poll: Duration,
+}
+
+impl<P: InputPin> EStopInput<P>
+{
+    /// Create a new `EStopInput` from the supplied pin, read every 10 ms
+    pub fn new(
+        pin: P,
+        active_low: bool,
+    ) -> Self
+    {
+        Self {
+            pin,
+            active_low,
+            poll: Duration::from_millis(10),
+        }
+    }
+
+    /// Set how often the pin is read
+    #[must_use]
+    pub fn with_poll(
+        mut self,
+        poll: Duration,
+    ) -> Self
+    {
+        self.poll = poll;
+        self
+    }
+
+    /// Whether the button is pressed
+    ///
+    /// A pin that cannot be read counts as pressed, so that a broken input
+    /// fails safe.
+    pub fn is_pressed(&mut self) -> bool
+    {
+        self.pin
+            .is_high()
+            .map_or(true, |high| high != self.active_low)
+    }
+
+    /// Wait until the button is pressed
+    pub async fn pressed(&mut self)
+    {
+        let mut ticker = Ticker::every(self.poll);
+
+        while !self.is_pressed() {
+            ticker.next().await;
+        }
+    }
+
+    /// Watch the button forever, calling `trip` whenever it is pressed
+    ///
+    /// `trip` keeps being called while the button is held, so the stop is
+    /// raised again if it is reset before the button is released.
+    pub async fn watch(
+        mut self,
+        trip: impl Fn(EStopSource),
+    ) -> !
+    {
+        let mut ticker = Ticker::every(self.poll);
+
+        loop {
+            if self.is_pressed() {
+                trip(EStopSource::Input);
+            }
+            ticker.next().await;
+        }
+    }
+}
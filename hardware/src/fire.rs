@@ -0,0 +1,768 @@
+//! ## Fire Control Module
+//!
+//! Coordinates the flywheels and the loader, so that a dart is only pushed
+//! into flywheels that are up to speed, and the flywheels spin down on their
+//! own once firing stops. Darts are fired as single shots, bursts or
+//! rate-limited full-auto, with the timing of each shot set by the type of
+//! loader. An optional [Magazine] counts the rounds left, and firing is
+//! refused once it is empty. An optional [JamSensor] watches each loader
+//! cycle, and a jam is cleared and then blocks firing until acknowledged.
+
+use core::{fmt, future::pending};
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+
+pub use self::{
+    jam::{CurrentTrip, CycleState, CycleSwitch, JamEvent, JamSensor},
+    magazine::{
+        Magazine,
+        MagazineEvent,
+        MagazineSensor,
+        MagazineState,
+        MagazineSwitch,
+        SwitchRole,
+    },
+};
+use crate::{
+    motor::{Direction, MotorFault},
+    LaunchProfile,
+    Motor,
+    MotorCommand,
+};
+
+mod jam;
+mod magazine;
+
+/// How often a magazine sensor is read while idle
+const MAGAZINE_POLL: Duration = Duration::from_millis(50);
+
+/// How often a jam sensor is checked while the loader runs
+const JAM_POLL: Duration = Duration::from_millis(5);
+
+/// Fire Command
+///
+/// Every firing command spins the flywheels up first if needed, and shots
+/// are never fired faster than the configured rounds-per-second cap.
+///
+/// Variants:
+/// - `Single`: Fire a single dart.
+///   - Ex: `{ "Fire": "Single" }`
+/// - `Burst(u8)`: Fire a burst of the given number of darts.
+///   - Ex: `{ "Fire": { "Burst": 3 } }`
+/// - `Auto`: Fire continuously until told to stop.
+///   - Ex: `{ "Fire": "Auto" }`
+/// - `Cease`: Stop firing, leaving the flywheels to spin down as usual.
+///   - Ex: `{ "Fire": "Cease" }`
+/// - `Reload`: Mark the magazine as full, for magazines without a sensor.
+///   - Ex: `{ "Fire": "Reload" }`
+/// - `Acknowledge`: Acknowledge a loader jam, allowing firing again.
+///   - Ex: `{ "Fire": "Acknowledge" }`
+/// - `SpinUp`: Spin the flywheels up ahead of firing.
+///   - Ex: `{ "Fire": "SpinUp" }`
+/// - `SpinDown`: Stop the flywheels and the loader straight away.
+///   - Ex: `{ "Fire": "SpinDown" }`
+#[derive(Copy, Clone, fmt::Debug, serde::Serialize, serde::Deserialize)]
+pub enum FireCommand
+{
+    Single,
+    Burst(u8),
+    Auto,
+    Cease,
+    Reload,
+    Acknowledge,
+    SpinUp,
+    SpinDown,
+}
+
+/// Shot Timing
+///
+/// How the loader fires a single dart, depending on its type.
+///
+/// Variants:
+/// - `Pusher { cycle }`: A cam-driven pusher, run for one revolution of the cam
+///   per dart.
+/// - `Solenoid { extend, retract }`: A solenoid pusher, energised for `extend`
+///   to push the dart, then released for `retract` so it returns before the
+///   next shot.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub enum ShotTiming
+{
+    Pusher
+    {
+        cycle: Duration
+    },
+    Solenoid
+    {
+        extend: Duration, retract: Duration
+    },
+}
+
+impl ShotTiming
+{
+    /// The loader sequence that fires a single dart
+    pub fn profile(&self) -> LaunchProfile
+    {
+        let (on, off) = match *self {
+            ShotTiming::Pusher { cycle } => (cycle, Duration::from_ticks(0)),
+            ShotTiming::Solenoid { extend, retract } => (extend, retract),
+        };
+
+        LaunchProfile {
+            pulses: 1,
+            on,
+            off,
+            spin_up: Duration::from_ticks(0),
+            ramp: 0,
+        }
+    }
+}
+
+/// Fire Config
+///
+/// # Fields
+/// - `spin_up`: How long the flywheels must have been running before the loader
+///   may fire, when readiness is not judged by RPM.
+/// - `spin_down`: How long the flywheels keep running after the last shot.
+/// - `ready_rpm`: The flywheel speed, in RPM, at which the loader may fire.
+///   Only used when the flywheels report their speed; otherwise `spin_up`
+///   applies.
+/// - `spin_up_timeout`: How long to wait for the flywheels to reach `ready_rpm`
+///   before giving up.
+/// - `shot`: How the loader fires a single dart.
+/// - `max_rps`: The most rounds per second the loader may fire, across bursts
+///   and full-auto. `0` leaves the rate limited only by the shot timing.
+/// - `clear_speed`: The speed, in percent, the loader reverses at to clear a
+///   jam. Loaders that cannot reverse stop instead.
+/// - `clear_time`: How long the loader reverses for to clear a jam.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct FireConfig
+{
+    pub spin_up: Duration,
+    pub spin_down: Duration,
+    pub ready_rpm: Option<f32>,
+    pub spin_up_timeout: Duration,
+    pub shot: ShotTiming,
+    pub max_rps: f32,
+    pub clear_speed: u8,
+    pub clear_time: Duration,
+}
+
+impl Default for FireConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            spin_up: Duration::from_millis(500),
+            spin_down: Duration::from_secs(3),
+            ready_rpm: None,
+            spin_up_timeout: Duration::from_secs(3),
+            shot: ShotTiming::Solenoid {
+                extend: Duration::from_millis(100),
+                retract: Duration::from_millis(100),
+            },
+            max_rps: 5.0,
+            clear_speed: 50,
+            clear_time: Duration::from_millis(150),
+        }
+    }
+}
+
+impl FireConfig
+{
+    /// The shortest time allowed between the start of two shots
+    pub fn shot_interval(&self) -> Duration
+    {
+        match self.max_rps > 0.0 {
+            true => Duration::from_micros((1_000_000.0 / self.max_rps) as u64),
+            false => Duration::from_ticks(0),
+        }
+    }
+}
+
+/// Fire Motor
+///
+/// Variants:
+/// - `Flywheels`: The flywheel motor, and the default.
+/// - `Loader`: The loader motor.
+#[derive(Copy, Clone, Default, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FireMotor
+{
+    #[default]
+    Flywheels,
+    Loader,
+}
+
+/// Fire Error
+#[derive(Debug)]
+pub enum FireError<F, L>
+{
+    FlywheelError(F),
+    LoaderError(L),
+    SpinUpTimeout,
+    Empty,
+    Jammed,
+}
+
+/// Fire Control
+///
+/// Owns the flywheels and the loader, and is the only way either of them
+/// should be driven. Firing spins the flywheels up first and only runs the
+/// loader once they are ready. After the last shot, the flywheels keep
+/// running for the spin-down time, so that follow-up shots fire straight
+/// away, and are then stopped by [FireControl::idle].
+///
+/// With a [Magazine] fitted, every loader cycle is counted, and firing is
+/// refused once the magazine is empty. Magazine changes are kept until taken
+/// with [FireControl::take_event], so they can be reported to clients.
+///
+/// With a [JamSensor] fitted, every loader cycle is watched for a jam. A
+/// jammed loader is reversed briefly to clear it, or just stopped if it
+/// cannot reverse, and firing is then refused until the jam is acknowledged.
+/// Jam changes are kept until taken with [FireControl::take_jam_event].
+///
+/// # Type Parameters
+/// - `F`: The flywheel motor.
+/// - `L`: The loader motor.
+/// - `S`: The magazine sensor, or `()` for none.
+/// - `J`: The jam sensor, or `()` for none.
+///
+/// # Fields
+/// - `flywheels`: The flywheel motor.
+/// - `loader`: The loader motor.
+/// - `config`: Spin-up, spin-down and shot timing.
+/// - `spinning`: When the flywheels were started, while they are running.
+/// - `idle_since`: When fire control last used the flywheels, while it is
+///   responsible for spinning them down.
+/// - `last_shot`: When the most recent shot started, for the rate cap.
+/// - `magazine`: The magazine, if rounds are being counted.
+/// - `event`: The most recent magazine change, until it is taken.
+/// - `jam`: The jam sensor.
+/// - `jammed`: Whether a jam is waiting to be acknowledged.
+/// - `jam_event`: The most recent jam change, until it is taken.
+pub struct FireControl<F: Motor, L: Motor, S: MagazineSensor = (), J: JamSensor = ()>
+{
+    flywheels: F,
+    loader: L,
+    config: FireConfig,
+    spinning: Option<Instant>,
+    idle_since: Option<Instant>,
+    last_shot: Option<Instant>,
+    magazine: Option<Magazine<S>>,
+    event: Option<MagazineEvent>,
+    jam: J,
+    jammed: bool,
+    jam_event: Option<JamEvent>,
+}
+
+impl<F: Motor, L: Motor> FireControl<F, L>
+{
+    /// Create a new `FireControl` from the supplied motors, with
+    /// [FireConfig::default]
+    pub fn new(
+        flywheels: F,
+        loader: L,
+    ) -> Self
+    {
+        Self {
+            flywheels,
+            loader,
+            config: FireConfig::default(),
+            spinning: None,
+            idle_since: None,
+            last_shot: None,
+            magazine: None,
+            event: None,
+            jam: (),
+            jammed: false,
+            jam_event: None,
+        }
+    }
+}
+
+impl<F: Motor, L: Motor, S: MagazineSensor, J: JamSensor> FireControl<F, L, S, J>
+{
+    /// Count rounds with the given magazine
+    #[must_use]
+    pub fn with_magazine<T: MagazineSensor>(
+        self,
+        magazine: Magazine<T>,
+    ) -> FireControl<F, L, T, J>
+    {
+        FireControl {
+            flywheels: self.flywheels,
+            loader: self.loader,
+            config: self.config,
+            spinning: self.spinning,
+            idle_since: self.idle_since,
+            last_shot: self.last_shot,
+            magazine: Some(magazine),
+            event: None,
+            jam: self.jam,
+            jammed: self.jammed,
+            jam_event: self.jam_event,
+        }
+    }
+
+    /// Watch the loader for jams with the given sensor
+    #[must_use]
+    pub fn with_jam_sensor<T: JamSensor>(
+        self,
+        jam: T,
+    ) -> FireControl<F, L, S, T>
+    {
+        FireControl {
+            flywheels: self.flywheels,
+            loader: self.loader,
+            config: self.config,
+            spinning: self.spinning,
+            idle_since: self.idle_since,
+            last_shot: self.last_shot,
+            magazine: self.magazine,
+            event: self.event,
+            jam,
+            jammed: false,
+            jam_event: None,
+        }
+    }
+
+    /// Set the spin-up, spin-down and shot timing
+    #[must_use]
+    pub fn with_config(
+        mut self,
+        config: FireConfig,
+    ) -> Self
+    {
+        self.config = config;
+        self
+    }
+
+    /// The flywheel motor
+    pub fn flywheels(&self) -> &F { &self.flywheels }
+
+    /// The loader motor
+    pub fn loader(&self) -> &L { &self.loader }
+
+    /// The magazine, if rounds are being counted
+    pub fn magazine(&self) -> Option<&Magazine<S>> { self.magazine.as_ref() }
+
+    /// Take the most recent magazine change
+    ///
+    /// # Returns
+    ///
+    /// * `Option<MagazineEvent>` - The change caused by the last command, if
+    ///   any.
+    pub fn take_event(&mut self) -> Option<MagazineEvent> { self.event.take() }
+
+    /// Whether a loader jam is waiting to be acknowledged
+    pub fn is_jammed(&self) -> bool { self.jammed }
+
+    /// Take the most recent jam change
+    ///
+    /// # Returns
+    ///
+    /// * `Option<JamEvent>` - The change caused by the last command, if any.
+    pub fn take_jam_event(&mut self) -> Option<JamEvent> { self.jam_event.take() }
+
+    /// Take the most recent current fault from either motor
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(FireMotor, MotorFault)>` - The motor that was cut, and why,
+    ///   if either has been cut since this was last called.
+    pub fn take_fault(&mut self) -> Option<(FireMotor, MotorFault)>
+    {
+        self.flywheels
+            .take_fault()
+            .map(|fault| (FireMotor::Flywheels, fault))
+            .or_else(|| {
+                self.loader
+                    .take_fault()
+                    .map(|fault| (FireMotor::Loader, fault))
+            })
+    }
+
+    /// Whether the flywheels are running
+    pub fn is_spinning(&self) -> bool { self.spinning.is_some() }
+
+    /// Whether the flywheels are ready for the loader to fire
+    pub fn is_ready(&self) -> bool
+    {
+        let Some(spinning) = self.spinning
+        else {
+            return false;
+        };
+
+        match (self.config.ready_rpm, self.flywheels.rpm()) {
+            (Some(ready), Some(rpm)) => rpm >= ready,
+            _ => spinning.elapsed() >= self.config.spin_up,
+        }
+    }
+
+    /// Spin the flywheels up, and wait until they are ready
+    pub async fn spin_up(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        let spinning = match self.spinning {
+            Some(spinning) => spinning,
+            None => {
+                let start = Instant::now();
+
+                self.flywheels
+                    .process(MotorCommand::On)
+                    .await
+                    .map_err(FireError::FlywheelError)?;
+                *self.spinning.insert(start)
+            }
+        };
+
+        self.idle_since = Some(Instant::now());
+
+        match (self.config.ready_rpm, self.flywheels.rpm()) {
+            (Some(_), Some(_)) => {
+                let mut ticker = Ticker::every(Duration::from_millis(10));
+
+                while !self.is_ready() {
+                    if spinning.elapsed() >= self.config.spin_up_timeout {
+                        return Err(FireError::SpinUpTimeout);
+                    }
+                    ticker.next().await;
+                }
+            }
+            _ => Timer::at(spinning + self.config.spin_up).await,
+        }
+        Ok(())
+    }
+
+    /// Fire the given number of darts, spinning up first if needed
+    pub async fn fire(
+        &mut self,
+        shots: u16,
+    ) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        self.check_ready()?;
+        self.spin_up().await?;
+
+        for _ in 0..shots {
+            self.shoot().await?;
+        }
+        Ok(())
+    }
+
+    /// Fire continuously, spinning up first if needed
+    ///
+    /// Never completes on its own; firing stops when the future is dropped,
+    /// after which [FireControl::cease] should be called to leave the loader
+    /// off.
+    pub async fn auto(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        self.check_ready()?;
+        self.spin_up().await?;
+
+        loop {
+            self.shoot().await?;
+        }
+    }
+
+    /// Stop firing, leaving the flywheels to spin down as usual
+    pub fn cease(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        if self.spinning.is_some() {
+            self.idle_since = Some(Instant::now());
+        }
+
+        self.loader.off().map_err(FireError::LoaderError)
+    }
+
+    /// Mark the magazine as full
+    pub fn reload(&mut self)
+    {
+        if let Some(magazine) = &mut self.magazine {
+            self.event = Some(magazine.reload());
+        }
+    }
+
+    /// Acknowledge a loader jam, allowing firing again
+    pub fn acknowledge(&mut self)
+    {
+        if self.jammed {
+            self.jammed = false;
+            self.jam_event = Some(JamEvent::Acknowledged);
+        }
+    }
+
+    /// Fire a single dart, once the rate cap allows it
+    async fn shoot(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        self.check_ready()?;
+
+        if let Some(last_shot) = self.last_shot {
+            Timer::at(last_shot + self.config.shot_interval()).await;
+        }
+
+        self.last_shot = Some(Instant::now());
+        let state = self.cycle().await.map_err(FireError::LoaderError)?;
+        self.idle_since = Some(Instant::now());
+
+        if state == CycleState::Jammed {
+            self.clear_jam().await?;
+            return Err(FireError::Jammed);
+        }
+
+        if let Some(event) = self.magazine.as_mut().and_then(Magazine::record_shot) {
+            self.event = Some(event);
+        }
+        Ok(())
+    }
+
+    /// Run the loader through a single shot, watching for a jam
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CycleState, L::Error>` - `Jammed` if the loader jammed,
+    ///   otherwise `Complete`, or an error if the loader could not be driven.
+    async fn cycle(&mut self) -> Result<CycleState, L::Error>
+    {
+        let profile = self.config.shot.profile();
+
+        if !J::FITTED {
+            self.loader.launch(profile).await?;
+            return Ok(CycleState::Complete);
+        }
+
+        let start = Instant::now();
+        let jam = &mut self.jam;
+        jam.start();
+
+        let watch = async {
+            let mut ticker = Ticker::every(JAM_POLL);
+
+            while jam.check(start.elapsed()) != CycleState::Jammed {
+                ticker.next().await;
+            }
+        };
+
+        if let Either::Second(()) = select(self.loader.launch(profile), watch).await {
+            return Ok(CycleState::Jammed);
+        }
+
+        // the loader has finished driving, but the sensor may not have seen
+        // the end of the cycle yet
+        let mut ticker = Ticker::every(JAM_POLL);
+
+        loop {
+            match self.jam.check(start.elapsed()) {
+                CycleState::Running => ticker.next().await,
+                state => return Ok(state),
+            }
+        }
+    }
+
+    /// Back the loader off a jam, and hold off firing until it is
+    /// acknowledged
+    async fn clear_jam(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        self.jammed = true;
+        self.jam_event = Some(JamEvent::Detected);
+
+        self.loader
+            .drive(Direction::Reverse, self.config.clear_speed)
+            .await
+            .map_err(FireError::LoaderError)?;
+        Timer::after(self.config.clear_time).await;
+        self.loader.off().map_err(FireError::LoaderError)
+    }
+
+    /// Refuse to fire while jammed, or if the magazine is empty
+    fn check_ready(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        if self.jammed {
+            return Err(FireError::Jammed);
+        }
+
+        let Some(magazine) = &mut self.magazine
+        else {
+            return Ok(());
+        };
+
+        if let Some(event) = magazine.poll() {
+            self.event = Some(event);
+        }
+
+        if magazine.is_empty() {
+            self.event = Some(MagazineEvent::Empty);
+            return Err(FireError::Empty);
+        }
+        Ok(())
+    }
+
+    /// Background work while waiting for the next command
+    ///
+    /// Stops the flywheels once the spin-down time has passed since they
+    /// were last used, watches the magazine sensor, if one is fitted, and
+    /// supervises both motors while they are left running.
+    /// Never completes while there is nothing to do, so it can be raced
+    /// against waiting for the next command.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<MagazineEvent>, FireError<F::Error, L::Error>>` - A
+    ///   change seen by the magazine sensor, or `None` once the flywheels have
+    ///   spun down.
+    pub async fn idle(&mut self) -> Result<Option<MagazineEvent>, FireError<F::Error, L::Error>>
+    {
+        let watching = S::FITTED && self.magazine.is_some();
+
+        loop {
+            if let Some(event) = self.magazine.as_mut().and_then(Magazine::poll) {
+                return Ok(Some(event));
+            }
+
+            let spin_down = self.idle_since.map(|idle| idle + self.config.spin_down);
+
+            if spin_down.is_some_and(|at| at <= Instant::now()) {
+                self.stop()?;
+                return Ok(None);
+            }
+
+            let poll = watching.then(|| Instant::now() + MAGAZINE_POLL);
+            let wake = match (spin_down, poll) {
+                (Some(at), Some(poll)) => Some(at.min(poll)),
+                (at, None) | (None, at) => at,
+            };
+
+            let wait = async {
+                match wake {
+                    Some(at) => Timer::at(at).await,
+                    None => pending().await,
+                }
+            };
+            let supervise = select(self.flywheels.supervise(), self.loader.supervise());
+
+            match select(wait, supervise).await {
+                Either::First(()) => {}
+                Either::Second(Either::First(result)) => {
+                    self.spinning = None;
+                    self.idle_since = None;
+                    result.map_err(FireError::FlywheelError)?;
+                }
+                Either::Second(Either::Second(result)) => {
+                    result.map_err(FireError::LoaderError)?;
+                }
+            }
+        }
+    }
+
+    /// Stop the loader and the flywheels straight away
+    pub fn stop(&mut self) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        let loader = self.loader.off();
+        let flywheels = self.flywheels.off();
+
+        self.spinning = None;
+        self.idle_since = None;
+
+        loader.map_err(FireError::LoaderError)?;
+        flywheels.map_err(FireError::FlywheelError)
+    }
+
+    /// Drive the flywheels directly
+    ///
+    /// Manual control takes the flywheels out of fire control's hands: they
+    /// are not spun down automatically until fire control is next used to
+    /// fire, but fire control still knows whether they are running.
+    pub async fn flywheel_command(
+        &mut self,
+        command: MotorCommand,
+    ) -> Result<(), F::Error>
+    {
+        let start = Instant::now();
+        let result = self.flywheels.process(command).await;
+
+        self.idle_since = None;
+        self.spinning = match (command, &result) {
+            (MotorCommand::On | MotorCommand::Speed(1..) | MotorCommand::Forward(1..), Ok(())) => {
+                self.spinning.or(Some(start))
+            }
+            _ => None,
+        };
+
+        result
+    }
+
+    /// Drive the loader directly
+    ///
+    /// Manual control of the loader, such as to back it off by hand, skips
+    /// the magazine and jam checks, and is not counted as a shot.
+    pub async fn loader_command(
+        &mut self,
+        command: MotorCommand,
+    ) -> Result<(), L::Error>
+    {
+        self.loader.process(command).await
+    }
+
+    /// Drive either motor directly
+    ///
+    /// # Parameters
+    ///
+    /// * `motor` - The motor the command applies to.
+    /// * `command` - The command for the motor.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), FireError<F::Error, L::Error>>` - Returns `Ok(())` if the
+    ///   command is successfully processed, or the motor's error.
+    pub async fn motor_command(
+        &mut self,
+        motor: FireMotor,
+        command: MotorCommand,
+    ) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        match motor {
+            FireMotor::Flywheels => self
+                .flywheel_command(command)
+                .await
+                .map_err(FireError::FlywheelError),
+            FireMotor::Loader => self
+                .loader_command(command)
+                .await
+                .map_err(FireError::LoaderError),
+        }
+    }
+
+    /// Process Commands
+    ///
+    /// # Parameters
+    ///
+    /// * `command` - A `FireCommand` instance representing the command to be
+    ///   processed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), FireError<F::Error, L::Error>>` - Returns `Ok(())` if the
+    ///   command is successfully processed, or an error if either motor fails,
+    ///   the flywheels do not get up to speed, the magazine is empty or the
+    ///   loader is jammed.
+    pub async fn process(
+        &mut self,
+        command: FireCommand,
+    ) -> Result<(), FireError<F::Error, L::Error>>
+    {
+        match command {
+            FireCommand::Single => self.fire(1).await,
+            FireCommand::Burst(shots) => self.fire(shots.into()).await,
+            FireCommand::Auto => self.auto().await,
+            FireCommand::Cease => self.cease(),
+            FireCommand::Reload => {
+                self.reload();
+                Ok(())
+            }
+            FireCommand::Acknowledge => {
+                self.acknowledge();
+                Ok(())
+            }
+            FireCommand::SpinUp => self.spin_up().await,
+            FireCommand::SpinDown => self.stop(),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! ## Jam Detection
+//!
+//! Watches each loader cycle for a jam, either from a cycle-complete switch
+//! that fails to trip in time, or from the loader drawing stall current.
+
+use core::fmt;
+
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::InputPin;
+
+use crate::motor::CurrentSensor;
+
+/// Cycle State
+///
+/// Variants:
+/// - `Running`: The loader cycle is still in progress.
+/// - `Complete`: The loader cycle has finished, as far as the sensor can tell.
+/// - `Jammed`: The loader is jammed.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub enum CycleState
+{
+    Running,
+    Complete,
+    Jammed,
+}
+
+/// Jam Event
+///
+/// Sent to clients when the loader jams, and when the jam is acknowledged.
+///
+/// Variants:
+/// - `Detected`: The loader jammed and was backed off. Firing is refused until
+///   the jam is acknowledged.
+///   - Ex: `{ "Jam": "Detected" }`
+/// - `Acknowledged`: The jam was acknowledged, and firing is allowed again.
+///   - Ex: `{ "Jam": "Acknowledged" }`
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum JamEvent
+{
+    Detected,
+    Acknowledged,
+}
+
+/// Jam Sensor
+///
+/// Something that can tell whether a loader cycle has jammed. `()` is used
+/// when there is no sensor, and jams go undetected.
+pub trait JamSensor
+{
+    /// Whether a sensor is actually fitted, and worth polling
+    const FITTED: bool = true;
+
+    /// Called as each loader cycle starts
+    fn start(&mut self) {}
+
+    /// Check on the loader cycle in progress
+    ///
+    /// Checked repeatedly while the loader runs, and afterwards until the
+    /// cycle is no longer `Running`.
+    ///
+    /// # Parameters
+    ///
+    /// * `elapsed` - Time since the cycle started.
+    ///
+    /// # Returns
+    ///
+    /// * `CycleState` - The state of the cycle.
+    fn check(
+        &mut self,
+        elapsed: Duration,
+    ) -> CycleState;
+}
+
+impl JamSensor for ()
+{
+    const FITTED: bool = false;
+
+    fn check(
+        &mut self,
+        _elapsed: Duration,
+    ) -> CycleState
+    {
+        CycleState::Complete
+    }
+}
+
+/// Cycle Switch
+///
+/// A switch that trips once per loader cycle, such as at the home position
+/// of a pusher cam or at the full extension of a solenoid. The cycle is
+/// complete once the switch is seen released and then tripped again, and
+/// jammed if that has not happened within the timeout.
+///
+/// # Type Parameters
+/// - `P`: The input pin the switch is connected to.
+///
+/// # Fields
+/// - `pin`: The input pin the switch is connected to.
+/// - `active_low`: Whether the switch pulls the pin low when tripped.
+/// - `timeout`: How long a cycle may take before it counts as jammed.
+/// - `released`: Whether the switch has been seen released this cycle.
+pub struct CycleSwitch<P: InputPin>
+{
+    pin: P,
+    active_low: bool,
+    timeout: Duration,
+    released: bool,
+}
+
+impl<P: InputPin> CycleSwitch<P>
+{
+    /// Create a new `CycleSwitch` from the supplied pin
+    pub fn new(
+        pin: P,
+        active_low: bool,
+        timeout: Duration,
+    ) -> Self
+    {
+        Self {
+            pin,
+            active_low,
+            timeout,
+            released: false,
+        }
+    }
+}
+
+impl<P: InputPin> JamSensor for CycleSwitch<P>
+{
+    fn start(&mut self) { self.released = false; }
+
+    fn check(
+        &mut self,
+        elapsed: Duration,
+    ) -> CycleState
+    {
+        match self.pin.is_high().map(|high| high != self.active_low) {
+            Ok(true) if self.released => return CycleState::Complete,
+            Ok(false) => self.released = true,
+            _ => {}
+        }
+
+        match elapsed >= self.timeout {
+            true => CycleState::Jammed,
+            false => CycleState::Running,
+        }
+    }
+}
+
+/// Current Trip
+///
+/// Detects a jam from the loader motor drawing stall current. The cycle
+/// counts as jammed once the current has stayed at or above the threshold
+/// for the hold time, which keeps the inrush at the start of each cycle
+/// from tripping it.
+///
+/// # Type Parameters
+/// - `C`: The current sensor on the loader motor.
+///
+/// # Fields
+/// - `sensor`: The current sensor on the loader motor.
+/// - `threshold`: The current, in amps, above which the loader is stalling.
+/// - `hold`: How long the current must stay above the threshold.
+/// - `over_since`: When the current went above the threshold, while it is.
+pub struct CurrentTrip<C: CurrentSensor>
+{
+    sensor: C,
+    threshold: f32,
+    hold: Duration,
+    over_since: Option<Instant>,
+}
+
+impl<C: CurrentSensor> CurrentTrip<C>
+{
+    /// Create a new `CurrentTrip`, with a 100 ms hold time
+    pub fn new(
+        sensor: C,
+        threshold: f32,
+    ) -> Self
+    {
+        Self {
+            sensor,
+            threshold,
+            hold: Duration::from_millis(100),
+            over_since: None,
+        }
+    }
+
+    /// Set how long the current must stay above the threshold
+    #[must_use]
+    pub fn with_hold(
+        mut self,
+        hold: Duration,
+    ) -> Self
+    {
+        self.hold = hold;
+        self
+    }
+}
+
+impl<C: CurrentSensor> JamSensor for CurrentTrip<C>
+{
+    fn start(&mut self) { self.over_since = None; }
+
+    fn check(
+        &mut self,
+        _elapsed: Duration,
+    ) -> CycleState
+    {
+        match self.sensor.amps() {
+            Some(amps) if amps >= self.threshold => {
+                let over_since = *self.over_since.get_or_insert_with(Instant::now);
+
+                match over_since.elapsed() >= self.hold {
+                    true => CycleState::Jammed,
+                    false => CycleState::Running,
+                }
+            }
+            _ => {
+                self.over_since = None;
+                CycleState::Complete
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! ## Magazine
+//!
+//! Tracks the rounds left in the magazine from loader cycles, optionally
+//! corrected by a switch that senses the magazine itself.
+
+use core::fmt;
+
+use embedded_hal::digital::InputPin;
+
+/// Magazine State
+///
+/// Variants:
+/// - `Seated`: A magazine is in place, and is not known to be empty.
+/// - `Removed`: No magazine is in place.
+/// - `Empty`: The magazine in place is empty.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MagazineState
+{
+    Seated,
+    Removed,
+    Empty,
+}
+
+/// Magazine Event
+///
+/// Sent to clients when the magazine changes.
+///
+/// Variants:
+/// - `Reloaded { remaining }`: The magazine was refilled.
+///   - Ex: `{ "Magazine": { "Reloaded": { "remaining": 12 } } }`
+/// - `Removed`: The magazine was taken out.
+///   - Ex: `{ "Magazine": "Removed" }`
+/// - `Empty`: The last round has been fired, or a shot was refused because the
+///   magazine is empty.
+///   - Ex: `{ "Magazine": "Empty" }`
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MagazineEvent
+{
+    Reloaded
+    {
+        remaining: u16,
+    },
+    Removed,
+    Empty,
+}
+
+/// Magazine Sensor
+///
+/// Something that can sense the state of the magazine. `()` is used when
+/// there is no sensor, and the magazine is tracked from loader cycles alone.
+pub trait MagazineSensor
+{
+    /// Whether a sensor is actually fitted, and worth polling
+    const FITTED: bool = true;
+
+    /// Read the state of the magazine
+    ///
+    /// # Returns
+    ///
+    /// * `Option<MagazineState>` - The sensed state, or `None` if it could not
+    ///   be read.
+    fn read(&mut self) -> Option<MagazineState>;
+}
+
+impl MagazineSensor for ()
+{
+    const FITTED: bool = false;
+
+    fn read(&mut self) -> Option<MagazineState> { None }
+}
+
+/// Switch Role
+///
+/// Variants:
+/// - `Seated`: The switch is active while a magazine is in place.
+/// - `Empty`: The switch is active once the magazine is empty, such as a switch
+///   under the follower.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub enum SwitchRole
+{
+    Seated,
+    Empty,
+}
+
+/// Magazine Switch
+///
+/// A magazine sensor on a single input pin.
+///
+/// # Type Parameters
+/// - `P`: The input pin the switch is connected to.
+///
+/// # Fields
+/// - `pin`: The input pin the switch is connected to.
+/// - `role`: What the switch being active means.
+/// - `active_low`: Whether the switch pulls the pin low when active, as with a
+///   switch to ground and a pull-up.
+pub struct MagazineSwitch<P: InputPin>
+{
+    pin: P,
+    role: SwitchRole,
+    active_low: bool,
+}
+
+impl<P: InputPin> MagazineSwitch<P>
+{
+    /// Create a new `MagazineSwitch` from the supplied pin
+    pub fn new(
+        pin: P,
+        role: SwitchRole,
+        active_low: bool,
+    ) -> Self
+    {
+        Self {
+            pin,
+            role,
+            active_low,
+        }
+    }
+}
+
+impl<P: InputPin> MagazineSensor for MagazineSwitch<P>
+{
+    fn read(&mut self) -> Option<MagazineState>
+    {
+        let active = self.pin.is_high().ok()? != self.active_low;
+
+        Some(match (self.role, active) {
+            (SwitchRole::Seated, true) | (SwitchRole::Empty, false) => MagazineState::Seated,
+            (SwitchRole::Seated, false) => MagazineState::Removed,
+            (SwitchRole::Empty, true) => MagazineState::Empty,
+        })
+    }
+}
+
+/// Magazine
+///
+/// Counts down the rounds left as the loader cycles. With a sensor fitted,
+/// seating a magazine refills the count, and the sensor can also report the
+/// magazine removed or empty before the count runs out.
+///
+/// # Type Parameters
+/// - `S`: The magazine sensor, or `()` for none.
+///
+/// # Fields
+/// - `sensor`: The magazine sensor.
+/// - `capacity`: Rounds in a full magazine.
+/// - `remaining`: Rounds left, as counted from loader cycles.
+/// - `state`: The state last read from the sensor, if any.
+pub struct Magazine<S: MagazineSensor = ()>
+{
+    sensor: S,
+    capacity: u16,
+    remaining: u16,
+    state: Option<MagazineState>,
+}
+
+impl Magazine
+{
+    /// Create a new, full `Magazine` with no sensor
+    pub fn new(capacity: u16) -> Self
+    {
+        Self {
+            sensor: (),
+            capacity,
+            remaining: capacity,
+            state: None,
+        }
+    }
+}
+
+impl<S: MagazineSensor> Magazine<S>
+{
+    /// Add a sensor to the magazine
+    #[must_use]
+    pub fn with_sensor<T: MagazineSensor>(
+        self,
+        sensor: T,
+    ) -> Magazine<T>
+    {
+        Magazine {
+            sensor,
+            capacity: self.capacity,
+            remaining: self.remaining,
+            state: None,
+        }
+    }
+
+    /// Rounds in a full magazine
+    pub fn capacity(&self) -> u16 { self.capacity }
+
+    /// Rounds left, as counted from loader cycles
+    pub fn remaining(&self) -> u16 { self.remaining }
+
+    /// The state last read from the sensor, if any
+    pub fn state(&self) -> Option<MagazineState> { self.state }
+
+    /// Whether there is nothing left to fire
+    pub fn is_empty(&self) -> bool
+    {
+        self.remaining == 0
+            || matches!(
+                self.state,
+                Some(MagazineState::Removed | MagazineState::Empty)
+            )
+    }
+
+    /// Refill the count to the magazine's capacity
+    pub fn reload(&mut self) -> MagazineEvent
+    {
+        self.remaining = self.capacity;
+
+        MagazineEvent::Reloaded {
+            remaining: self.remaining,
+        }
+    }
+
+    /// Count a single loader cycle
+    ///
+    /// # Returns
+    ///
+    /// * `Option<MagazineEvent>` - `Empty` if that was the last round.
+    pub fn record_shot(&mut self) -> Option<MagazineEvent>
+    {
+        self.remaining = self.remaining.saturating_sub(1);
+
+        match self.remaining {
+            0 => Some(MagazineEvent::Empty),
+            _ => None,
+        }
+    }
+
+    /// Read the sensor, and update the magazine to match
+    ///
+    /// A magazine being seated, after having been removed or empty, counts
+    /// as a reload.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<MagazineEvent>` - The change seen since the last read, if any.
+    pub fn poll(&mut self) -> Option<MagazineEvent>
+    {
+        if !S::FITTED {
+            return None;
+        }
+
+        let state = self.sensor.read()?;
+        let previous = self.state.replace(state);
+
+        match (previous, state) {
+            (Some(previous), state) if previous == state => None,
+            (None, MagazineState::Seated) => None,
+            (Some(_), MagazineState::Seated) => Some(self.reload()),
+            (_, MagazineState::Removed) => Some(MagazineEvent::Removed),
+            (_, MagazineState::Empty) => {
+                self.remaining = 0;
+                Some(MagazineEvent::Empty)
+            }
+        }
+    }
+}
@@ -6,9 +6,16 @@
 //! This crate provides the interface for interacting with a robot's hardware
 //! components. It includes submodules for specific hardware control:
 //!
-//! * **motor:** Functions for controlling a motor (On, Off, Launch).
+//! * **motor:** Functions for controlling a motor (On, Off, Launch, Speed,
+//!   Forward, Reverse, Brake, Coast), with optional stall, over-current and
+//!   thermal protection.
 //! * **servo:** Fine-grained servo control, including configuration, angle
-//!   mapping, and smooth movement.
+//!   mapping, and smooth movement, with steppers as an alternative to servos on
+//!   either axis.
+//! * **fire:** Fire control, coordinating the flywheels and the loader (Single,
+//!   Burst, Auto, Cease, Reload, Acknowledge, SpinUp, SpinDown), with magazine
+//!   tracking and jam detection.
+//! * **estop:** Emergency stop sources, including a GPIO e-stop input.
 
 /// Motor Module
 ///
@@ -21,11 +28,26 @@ pub mod motor;
 /// Servo Module
 ///
 /// This module offers detailed control over servo motors. It includes
-/// functionality for configuring servos, mapping angles, and ensuring smooth
-/// movements. The module defines the `Servo` trait, `ServoCommand` enum, and
-/// `ServoPair` struct for managing servo operations.
+/// functionality for configuring servos, mapping angles, ensuring smooth
+/// movements, and keeping them within soft limits. The module defines the
+/// `Servo` trait, `ServoCommand` enum, and `ServoPair` struct for managing
+/// servo operations.
 pub mod servo;
 
+/// Fire Control Module
+///
+/// This module coordinates the flywheel and loader motors. It only lets the
+/// loader fire once the flywheels are up to speed, and spins them down after
+/// the last shot. The module defines the `FireCommand` enum and the
+/// `FireControl` struct that firing requests go through.
+pub mod fire;
+
+/// Emergency Stop Module
+///
+/// This module describes what can trigger an emergency stop, and provides
+/// the `EStopInput` struct for a physical e-stop button on a GPIO input.
+pub mod estop;
+
 // ESP32 target
 #[cfg(all(
     feature = "mcu",
@@ -51,8 +73,9 @@ pub use rr_hardware_mcu_local as board;
 pub use rr_hardware_mcu_rp2040 as board;
 
 pub use crate::{
-    motor::{Motor, MotorCommand},
-    servo::{Servo, ServoCommand, ServoPair},
+    fire::{FireCommand, FireControl},
+    motor::{LaunchProfile, Motor, MotorCommand},
+    servo::{Pose, Servo, ServoCalibration, ServoCommand, ServoPair},
 };
 #[cfg(feature = "mcu")]
 pub mod mcu
@@ -62,7 +85,14 @@ pub mod mcu
 
     #[cfg(target_arch = "xtensa")]
     pub use super::board::{connection, main};
-    use super::{board::MCU, Motor, ServoPair};
+    use super::{
+        board::MCU,
+        fire::{JamSensor, MagazineSensor},
+        motor::CurrentSensor,
+        servo::ServoAxis,
+        Motor,
+        ServoPair,
+    };
 
     pub trait MCUConfig<
         WifiDriver: Driver,
@@ -70,49 +100,114 @@ pub mod mcu
         Loader: Motor,
         Pan: SetDutyCycle,
         Tilt: SetDutyCycle,
+        Magazine: MagazineSensor,
+        Jam: JamSensor,
+        FlywheelCurrent: CurrentSensor,
+        LoaderCurrent: CurrentSensor,
     >
     {
-        fn components(self) -> MCUComponents<WifiDriver, Flywheels, Loader, Pan, Tilt>;
+        fn components(
+            self
+        ) -> MCUComponents<
+            WifiDriver,
+            Flywheels,
+            Loader,
+            Pan,
+            Tilt,
+            Magazine,
+            Jam,
+            FlywheelCurrent,
+            LoaderCurrent,
+        >;
     }
 
+    /// MCU Components
+    ///
+    /// The board's peripherals, ready for use. Sensors the board doesn't have
+    /// are `()`, or [no_current] for current sensors.
     pub struct MCUComponents<
         WifiDriver: Driver,
         Flywheels: Motor,
         Loader: Motor,
         Pan: SetDutyCycle,
         Tilt: SetDutyCycle,
+        Magazine: MagazineSensor,
+        Jam: JamSensor,
+        FlywheelCurrent: CurrentSensor,
+        LoaderCurrent: CurrentSensor,
     > {
         pub wifi_driver: WifiDriver,
         pub flywheels: Flywheels,
         pub loader: Loader,
-        pub servos: ServoPair<Pan, Tilt>,
+        pub servos: ServoPair<ServoAxis<Pan>, ServoAxis<Tilt>>,
+        pub magazine: Magazine,
+        pub jam: Jam,
+        pub flywheel_current: FlywheelCurrent,
+        pub loader_current: LoaderCurrent,
     }
 
+    /// Current sensor for a motor without one, which never has a reading
+    pub fn no_current() -> Option<f32> { None }
+
     impl<
             WifiDriver: Driver,
             Flywheels: Motor,
             Loader: Motor,
             Pan: SetDutyCycle,
             Tilt: SetDutyCycle,
-        > MCUConfig<WifiDriver, Flywheels, Loader, Pan, Tilt>
-        for MCU<WifiDriver, Flywheels, Loader, Pan, Tilt>
+        >
+        MCUConfig<
+            WifiDriver,
+            Flywheels,
+            Loader,
+            Pan,
+            Tilt,
+            (),
+            (),
+            fn() -> Option<f32>,
+            fn() -> Option<f32>,
+        > for MCU<WifiDriver, Flywheels, Loader, Pan, Tilt>
     {
-        fn components(self) -> MCUComponents<WifiDriver, Flywheels, Loader, Pan, Tilt>
+        fn components(
+            self
+        ) -> MCUComponents<
+            WifiDriver,
+            Flywheels,
+            Loader,
+            Pan,
+            Tilt,
+            (),
+            (),
+            fn() -> Option<f32>,
+            fn() -> Option<f32>,
+        >
         {
             MCUComponents {
                 wifi_driver: self.wifi_driver,
                 flywheels: self.flywheels,
                 loader: self.loader,
-                servos: ServoPair {
-                    pan: self.pan,
-                    tilt: self.tilt,
-                },
+                servos: ServoPair::from_pwm(self.pan, self.tilt),
+                // no board wires up a magazine switch, jam sensor or current
+                // sensing yet
+                magazine: (),
+                jam: (),
+                flywheel_current: no_current,
+                loader_current: no_current,
             }
         }
     }
 
-    pub fn init_mcu(
-    ) -> MCUComponents<impl Driver, impl Motor, impl Motor, impl SetDutyCycle, impl SetDutyCycle>
+    pub fn init_mcu() -> MCUComponents<
+        impl Driver,
+        impl Motor,
+        impl Motor,
+        impl SetDutyCycle,
+        impl SetDutyCycle,
+        impl MagazineSensor,
+        impl JamSensor,
+        impl CurrentSensor,
+        impl CurrentSensor,
+    >
     {
         let mcu = MCU::init();
         mcu.components()
@@ -2,12 +2,48 @@
 //!
 //! Adds basic single-motor control functionality, including
 //! configurable launch sequences to any type that implements
-//! [OutputPin](embedded_hal::digital::OutputPin).
+//! [OutputPin](embedded_hal::digital::OutputPin). Motors can be given their
+//! own [LaunchProfile] with [Motor::with_profile], and speed-controlled motors
+//! driven through [SetDutyCycle](embedded_hal::pwm::SetDutyCycle) are
+//! provided by [PwmMotor]. Brushless motors behind hobby ESCs are driven by
+//! [Esc], using either servo-style PWM or DShot, and brushed motors that need
+//! to reverse or brake are driven through an H-bridge by [HBridgeMotor].
+//! Motors with a [Tachometer] can be held at a target RPM by [ClosedLoop],
+//! and [FlywheelModel] stands in for one on the host. Motors with a
+//! [CurrentSensor] can be cut on a stall or over-current by [Protected], and
+//! [CurrentTrace] replays simulated current on the host. [Thermal] holds
+//! motors and solenoids to a thermal budget, so they cannot overheat.
 
-use core::fmt;
+use core::{fmt, future::pending};
 
-use embassy_time::{Duration, Timer};
 use embedded_hal::digital::OutputPin;
+use serde::de::{self, value::MapAccessDeserializer, IntoDeserializer};
+
+pub use self::{
+    closed_loop::{ClosedLoop, RpmController},
+    current::{AdcCurrent, CurrentSensor},
+    dshot::{DshotFrame, DshotOutput, DshotSpeed},
+    esc::{DshotSignal, Esc, EscError, EscSignal, ServoPwmSignal},
+    hbridge::{Direction, DualPwmBridge, HBridge, HBridgeMotor, PwmDirBridge, PwmDirError},
+    launch::{LaunchOverrides, LaunchProfile},
+    model::{FlywheelModel, ModelCounter, ModelMotor},
+    protection::{CurrentLimits, CurrentTrace, Protected, ProtectionError},
+    pwm::{PwmMotor, SpeedRamp},
+    tachometer::{InterruptCounter, PulseCounter, QuadratureDecoder, Tachometer},
+    thermal::{HeatModel, OnTimeWindow, Thermal, ThermalError, ThermalModel, ThermalPolicy},
+};
+
+mod closed_loop;
+mod current;
+mod dshot;
+mod esc;
+mod hbridge;
+mod launch;
+mod model;
+mod protection;
+mod pwm;
+mod tachometer;
+mod thermal;
 
 /// Motor Command
 ///
@@ -16,14 +52,125 @@ use embedded_hal::digital::OutputPin;
 ///   - Ex: `{ "Motor": "On" }`
 /// - `Off`: Turn the motor off.
 ///   - Ex: `{ "Motor": "Off" }`
-/// - `Launch`: Launch the motor with a predefined sequence.
+/// - `Launch(LaunchOverrides)`: Run the motor's launch profile, optionally
+///   overriding some of its parameters.
 ///   - Ex: `{ "Motor": "Launch" }`
-#[derive(Copy, Clone, fmt::Debug, serde::Serialize, serde::Deserialize)]
+///   - Ex: `{ "Motor": { "Launch": {} } }`
+///   - Ex: `{ "Motor": { "Launch": { "pulses": 3 } } }`
+/// - `Abort`: Cancel any long-running operation and turn the motor off.
+///   - Ex: `{ "Motor": "Abort" }`
+/// - `Speed(u8)`: Run the motor at a percentage of full speed. Motors without
+///   speed control treat any non-zero speed as `On`.
+///   - Ex: `{ "Motor": { "Speed": 60 } }`
+/// - `Forward(u8)`: Run the motor forward at a percentage of full speed.
+///   - Ex: `{ "Motor": { "Forward": 60 } }`
+/// - `Reverse(u8)`: Run the motor in reverse at a percentage of full speed.
+///   Motors that cannot reverse stop instead.
+///   - Ex: `{ "Motor": { "Reverse": 60 } }`
+/// - `Brake`: Stop the motor actively. Motors that cannot brake turn off.
+///   - Ex: `{ "Motor": "Brake" }`
+/// - `Coast`: Turn the motor off and let it spin down freely.
+///   - Ex: `{ "Motor": "Coast" }`
+#[derive(Copy, Clone, fmt::Debug, serde::Serialize)]
 pub enum MotorCommand
 {
     On,
     Off,
-    Launch,
+    Launch(LaunchOverrides),
+    Abort,
+    Speed(u8),
+    Forward(u8),
+    Reverse(u8),
+    Brake,
+    Coast,
+}
+
+impl<'de> serde::Deserialize<'de> for MotorCommand
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_any(MotorCommandVisitor)
+    }
+}
+
+/// The wire format of [MotorCommand], as derived
+#[derive(serde::Deserialize)]
+#[serde(remote = "MotorCommand")]
+enum MotorCommandDef
+{
+    On,
+    Off,
+    Launch(LaunchOverrides),
+    Abort,
+    Speed(u8),
+    Forward(u8),
+    Reverse(u8),
+    Brake,
+    Coast,
+}
+
+/// Reads a [MotorCommand], also accepting a bare `"Launch"` without overrides
+struct MotorCommandVisitor;
+
+impl<'de> de::Visitor<'de> for MotorCommandVisitor
+{
+    type Value = MotorCommand;
+
+    fn expecting(
+        &self,
+        formatter: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        formatter.write_str("a motor command")
+    }
+
+    fn visit_str<E: de::Error>(
+        self,
+        name: &str,
+    ) -> Result<MotorCommand, E>
+    {
+        match name {
+            "Launch" => Ok(MotorCommand::Launch(LaunchOverrides::default())),
+            _ => MotorCommandDef::deserialize(name.into_deserializer()),
+        }
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(
+        self,
+        map: A,
+    ) -> Result<MotorCommand, A::Error>
+    {
+        MotorCommandDef::deserialize(MapAccessDeserializer::new(map))
+    }
+}
+
+/// Motor Fault
+///
+/// Why a motor was cut, or refused a command, by its protection.
+///
+/// Variants:
+/// - `Stall { amps }`: The motor stayed above its stall current for too long.
+///   - Ex: `{ "Stall": { "amps": 3.4 } }`
+/// - `OverCurrent { amps }`: The motor reached its maximum current.
+///   - Ex: `{ "OverCurrent": { "amps": 8.2 } }`
+/// - `Overheated { budget }`: The motor used up its thermal budget, with
+///   `budget` left, from 0 to 1.
+///   - Ex: `{ "Overheated": { "budget": 0.0 } }`
+#[derive(Copy, Clone, fmt::Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum MotorFault
+{
+    Stall
+    {
+        amps: f32
+    },
+    OverCurrent
+    {
+        amps: f32
+    },
+    Overheated
+    {
+        budget: f32
+    },
 }
 
 /// Motor Trait
@@ -34,6 +181,10 @@ pub enum MotorCommand
 ///
 /// The `Motor` trait is designed to be implemented for various types of motors,
 /// allowing for flexibility and extensibility in motor control implementations.
+///
+/// Long-running operations such as `launch` are cancelled by dropping their
+/// future, which may leave the motor on; callers that cancel an operation
+/// should follow up with `off` to leave the motor in a known state.
 #[allow(async_fn_in_trait)]
 pub trait Motor
 {
@@ -65,23 +216,168 @@ pub trait Motor
     ///   operation fails.
     fn off(&mut self) -> Result<(), Self::Error>;
 
+    /// Brake the motor
+    ///
+    /// Stops the motor actively rather than letting it spin down. The default
+    /// implementation is for motors that cannot brake, and turns the motor
+    /// off.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the motor is
+    ///   successfully braked, or an error of type `Self::Error` if the
+    ///   operation fails.
+    fn brake(&mut self) -> Result<(), Self::Error> { self.off() }
+
+    /// Start the motor
+    ///
+    /// Brings the motor up to its running speed, as for `MotorCommand::On`
+    /// and a launch's spin-up. The default implementation turns the motor on
+    /// straight away; motors that ramp, regulate or need arming first
+    /// override it.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the motor is
+    ///   successfully started, or an error of type `Self::Error` if the
+    ///   operation fails.
+    async fn start(&mut self) -> Result<(), Self::Error> { self.on() }
+
     /// Execute a customizable launch sequence
     ///
     /// This asynchronous method allows for the execution of a launch sequence,
     /// which could involve rapid toggling or other initialization routines
-    /// specific to the motor being controlled.
+    /// specific to the motor being controlled. The default implementation
+    /// runs the profile with [LaunchProfile::run].
+    ///
+    /// # Parameters
+    ///
+    /// * `profile` - The `LaunchProfile` describing the sequence to run.
     ///
     /// # Returns
     ///
     /// * `Result<(), Self::Error>` - Returns `Ok(())` if the launch sequence is
     ///   successfully executed, or an error of type `Self::Error` if the
     ///   operation fails.
-    async fn launch(&mut self) -> Result<(), Self::Error>;
+    async fn launch(
+        &mut self,
+        profile: LaunchProfile,
+    ) -> Result<(), Self::Error>
+    {
+        profile.run(self).await
+    }
+
+    /// Set the motor's speed
+    ///
+    /// Runs the motor at the given percentage of full speed. The default
+    /// implementation is for motors without speed control, and turns the
+    /// motor on for any non-zero speed.
+    ///
+    /// # Parameters
+    ///
+    /// * `percent` - The speed, from 0 to 100 percent.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the speed is
+    ///   successfully set, or an error of type `Self::Error` if the operation
+    ///   fails.
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        match percent {
+            0 => self.off(),
+            _ => self.on(),
+        }
+    }
+
+    /// Drive the motor in a given direction
+    ///
+    /// Runs the motor at the given percentage of full speed in either
+    /// direction. The default implementation is for motors that only turn one
+    /// way: it sets the speed going forward, and turns the motor off rather
+    /// than running it the wrong way in reverse.
+    ///
+    /// # Parameters
+    ///
+    /// * `direction` - The direction to turn the motor.
+    /// * `percent` - The speed, from 0 to 100 percent.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the motor is
+    ///   successfully driven, or an error of type `Self::Error` if the
+    ///   operation fails.
+    async fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        match direction {
+            Direction::Forward => self.set_speed(percent).await,
+            Direction::Reverse => self.off(),
+        }
+    }
+
+    /// The motor's measured speed
+    ///
+    /// Motors without speed feedback report `None`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<f32>` - The measured speed, in revolutions per minute, if the
+    ///   motor can measure it.
+    fn rpm(&self) -> Option<f32> { None }
+
+    /// The motor's measured current
+    ///
+    /// Motors without current sensing report `None`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<f32>` - The most recent current reading, in amps, if the motor
+    ///   can measure it.
+    fn current(&self) -> Option<f32> { None }
+
+    /// The motor's remaining thermal budget
+    ///
+    /// Motors without a thermal limit report `None`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<f32>` - The remaining budget, from 1 when cold to 0 when used
+    ///   up, if the motor has a thermal limit.
+    fn thermal_budget(&self) -> Option<f32> { None }
+
+    /// Take the most recent current fault
+    ///
+    /// # Returns
+    ///
+    /// * `Option<MotorFault>` - Why the motor was last cut, if it has been cut
+    ///   since this was last called.
+    fn take_fault(&mut self) -> Option<MotorFault> { None }
+
+    /// Watch over the motor while it is left running between commands
+    ///
+    /// Never completes for motors without protection. Protected motors
+    /// complete with an error once they have been cut, so this can be raced
+    /// against waiting for the next command.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - An error once the motor has been cut, or
+    ///   if it could not be turned off.
+    async fn supervise(&mut self) -> Result<(), Self::Error> { pending().await }
 
     /// Process Commands
     ///
-    /// This method processes commands sent to the servo. The `MotorCommand`
-    /// parameter represents the specific command to be executed.
+    /// This method processes commands sent to the motor. The `MotorCommand`
+    /// parameter represents the specific command to be executed. The default
+    /// implementation maps each command onto the methods above, so motors
+    /// only override those that behave differently.
     ///
     /// # Parameters
     ///
@@ -96,38 +392,169 @@ pub trait Motor
     async fn process(
         &mut self,
         command: MotorCommand,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<(), Self::Error>
+    {
+        match command {
+            MotorCommand::On => self.start().await,
+            MotorCommand::Off | MotorCommand::Abort | MotorCommand::Coast => self.off(),
+            MotorCommand::Brake => self.brake(),
+            MotorCommand::Speed(percent) => self.set_speed(percent).await,
+            MotorCommand::Forward(percent) => self.drive(Direction::Forward, percent).await,
+            MotorCommand::Reverse(percent) => self.drive(Direction::Reverse, percent).await,
+            MotorCommand::Launch(overrides) => {
+                self.launch(overrides.apply(LaunchProfile::default())).await
+            }
+        }
+    }
+
+    /// Give the motor its own launch profile
+    ///
+    /// Wraps the motor in a [Profiled] motor, which runs `profile` for every
+    /// `MotorCommand::Launch` instead of [LaunchProfile::default].
+    fn with_profile(
+        self,
+        profile: LaunchProfile,
+    ) -> Profiled<Self>
+    where
+        Self: Sized,
+    {
+        Profiled {
+            motor: self,
+            profile,
+        }
+    }
+
+    /// Protect the motor against stalls and over-current
+    ///
+    /// Wraps the motor in a [Protected] motor, which cuts it if the current
+    /// measured by `sensor` exceeds `limits`.
+    fn with_protection<C: CurrentSensor>(
+        self,
+        sensor: C,
+        limits: CurrentLimits,
+    ) -> Protected<Self, C>
+    where
+        Self: Sized,
+    {
+        Protected::new(self, sensor, limits)
+    }
+
+    /// Hold the motor to a thermal budget
+    ///
+    /// Wraps the motor in a [Thermal] motor, which tracks its heat with
+    /// `model` and applies `policy` to commands that do not fit in the
+    /// budget.
+    fn with_thermal_limit<T: ThermalModel>(
+        self,
+        model: T,
+        policy: ThermalPolicy,
+    ) -> Thermal<Self, T>
+    where
+        Self: Sized,
+    {
+        Thermal::new(self, model, policy)
+    }
 }
 
-impl<T: OutputPin> Motor for T
+/// Profiled Motor
+///
+/// A motor paired with the launch profile it should use. Launch overrides
+/// received over the wire are applied on top of this profile.
+///
+/// # Type Parameters
+/// - `M`: The motor being wrapped.
+///
+/// # Fields
+/// - `motor`: The wrapped motor.
+/// - `profile`: The motor's launch profile.
+pub struct Profiled<M: Motor>
 {
-    type Error = T::Error;
+    motor: M,
+    profile: LaunchProfile,
+}
 
-    fn on(&mut self) -> Result<(), Self::Error> { self.set_high() }
+impl<M: Motor> Profiled<M>
+{
+    /// The motor's launch profile
+    pub fn profile(&self) -> LaunchProfile { self.profile }
 
-    fn off(&mut self) -> Result<(), Self::Error> { self.set_low() }
+    /// Replace the motor's launch profile
+    pub fn set_profile(
+        &mut self,
+        profile: LaunchProfile,
+    )
+    {
+        self.profile = profile;
+    }
+
+    /// Unwrap the motor, discarding its profile
+    pub fn into_inner(self) -> M { self.motor }
+}
+
+impl<M: Motor> Motor for Profiled<M>
+{
+    type Error = M::Error;
+
+    fn on(&mut self) -> Result<(), Self::Error> { self.motor.on() }
+
+    fn off(&mut self) -> Result<(), Self::Error> { self.motor.off() }
 
-    // TODO(mguerrier): configure launch sequence for smooth transition
-    async fn launch(&mut self) -> Result<(), Self::Error>
+    fn brake(&mut self) -> Result<(), Self::Error> { self.motor.brake() }
+
+    async fn start(&mut self) -> Result<(), Self::Error> { self.motor.start().await }
+
+    async fn launch(
+        &mut self,
+        profile: LaunchProfile,
+    ) -> Result<(), Self::Error>
     {
-        for _ in 0..100 {
-            self.set_high()?;
-            Timer::after(Duration::from_millis(100)).await;
-            self.set_low()?;
-            Timer::after(Duration::from_millis(100)).await;
-        }
-        Ok(())
+        self.motor.launch(profile).await
     }
 
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        self.motor.set_speed(percent).await
+    }
+
+    async fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        self.motor.drive(direction, percent).await
+    }
+
+    fn rpm(&self) -> Option<f32> { self.motor.rpm() }
+
+    fn current(&self) -> Option<f32> { self.motor.current() }
+
+    fn thermal_budget(&self) -> Option<f32> { self.motor.thermal_budget() }
+
+    fn take_fault(&mut self) -> Option<MotorFault> { self.motor.take_fault() }
+
+    async fn supervise(&mut self) -> Result<(), Self::Error> { self.motor.supervise().await }
+
     async fn process(
         &mut self,
         command: MotorCommand,
     ) -> Result<(), Self::Error>
     {
         match command {
-            MotorCommand::On => self.on(),
-            MotorCommand::Off => self.off(),
-            MotorCommand::Launch => Ok(self.launch().await?),
+            MotorCommand::Launch(overrides) => self.launch(overrides.apply(self.profile)).await,
+            command => self.motor.process(command).await,
         }
     }
 }
+
+impl<T: OutputPin> Motor for T
+{
+    type Error = T::Error;
+
+    fn on(&mut self) -> Result<(), Self::Error> { self.set_high() }
+
+    fn off(&mut self) -> Result<(), Self::Error> { self.set_low() }
+}
@@ -0,0 +1,517 @@
+//! ## Closed-Loop Speed Control
+//!
+//! Holds a motor at a target RPM using [Tachometer] feedback, so that every
+//! shot leaves flywheels spinning at the same speed.
+
+use core::{cell::RefCell, fmt};
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+
+use super::{LaunchProfile, Motor, MotorFault, PulseCounter, Tachometer};
+
+/// RPM Controller
+///
+/// A PI controller with feed-forward, turning an RPM error into a motor
+/// speed in percent. The controller holds no timing of its own, so it can be
+/// stepped on the host, for example against a
+/// [FlywheelModel](super::FlywheelModel).
+///
+/// # Fields
+/// - `kp`: Proportional gain, in percent per RPM of error.
+/// - `ki`: Integral gain, in percent per RPM of error per second.
+/// - `feed_forward`: Open-loop speed, in percent per RPM of target; usually
+///   `100 / max_rpm`.
+/// - `integral`: The accumulated integral term, in percent.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct RpmController
+{
+    pub kp: f32,
+    pub ki: f32,
+    pub feed_forward: f32,
+    integral: f32,
+}
+
+impl RpmController
+{
+    /// Create a new `RpmController` from the supplied gains
+    pub const fn new(
+        kp: f32,
+        ki: f32,
+        feed_forward: f32,
+    ) -> Self
+    {
+        Self {
+            kp,
+            ki,
+            feed_forward,
+            integral: 0.0,
+        }
+    }
+
+    /// Clear the integral term, such as after the motor has been stopped
+    pub fn reset(&mut self) { self.integral = 0.0; }
+
+    /// Compute the motor speed for one control step
+    ///
+    /// # Parameters
+    ///
+    /// * `target` - The target speed, in RPM.
+    /// * `measured` - The measured speed, in RPM.
+    /// * `elapsed` - Seconds since the previous step.
+    ///
+    /// # Returns
+    ///
+    /// * `f32` - The motor speed, from 0 to 100 percent.
+    pub fn update(
+        &mut self,
+        target: f32,
+        measured: f32,
+        elapsed: f32,
+    ) -> f32
+    {
+        let error = target - measured;
+        let integral = self.integral + self.ki * error * elapsed;
+        let output = target * self.feed_forward + self.kp * error + integral;
+
+        // only keep integrating while the output is not saturated, or while
+        // the error is pulling it back, so the integral cannot wind up
+        if (0.0..=100.0).contains(&output) || (output > 100.0) == (error < 0.0) {
+            self.integral = integral;
+        }
+
+        output.clamp(0.0, 100.0)
+    }
+}
+
+impl Default for RpmController
+{
+    /// Gains for a typical 30 000 RPM flywheel motor
+    fn default() -> Self { Self::new(0.005, 0.02, 100.0 / 30_000.0) }
+}
+
+/// Closed-Loop Motor
+///
+/// A speed-controlled motor regulated to a target RPM from tachometer
+/// feedback. Speeds given in percent are taken as a percentage of
+/// `max_rpm`.
+///
+/// `On` and `Speed` regulate until the motor settles at the target (or the
+/// settle timeout passes), and `Launch` keeps regulating through its spin-up
+/// and pulses. Between commands, regulation carries on for as long as the
+/// motor is supervised with [Motor::supervise], as fire control does while
+/// it waits; otherwise the motor holds its last speed open-loop. The speed
+/// is sampled whenever it is read, at most once per control period, so
+/// [Motor::rpm] never reports a stale reading.
+///
+/// The inner motor should respond to [Motor::set_speed] straight away, such
+/// as a [PwmMotor](super::PwmMotor) with a zero [SpeedRamp](super::SpeedRamp),
+/// since the controller already limits how quickly the speed changes.
+///
+/// # Type Parameters
+/// - `M`: The speed-controlled motor being regulated.
+/// - `C`: The pulse counter behind the tachometer.
+///
+/// # Fields
+/// - `motor`: The speed-controlled motor being regulated.
+/// - `tachometer`: Feedback from the motor, sampled whenever it is read.
+/// - `controller`: The speed controller.
+/// - `max_rpm`: The speed, in RPM, that 100% corresponds to.
+/// - `tolerance`: How close, in RPM, the motor must be to count as at speed.
+/// - `period`: How often the controller runs.
+/// - `settle_timeout`: How long `On` and `Speed` wait for the motor to settle.
+/// - `target`: The target speed, in RPM; `0` when off.
+/// - `cruise`: The speed `On` runs at; the last non-zero commanded speed.
+/// - `last_step`: When the controller last ran, while it is regulating.
+pub struct ClosedLoop<M: Motor, C: PulseCounter>
+{
+    motor: M,
+    tachometer: RefCell<Tachometer<C>>,
+    controller: RpmController,
+    max_rpm: f32,
+    tolerance: f32,
+    period: Duration,
+    settle_timeout: Duration,
+    target: f32,
+    cruise: u8,
+    last_step: Option<Instant>,
+}
+
+impl<M: Motor, C: PulseCounter> ClosedLoop<M, C>
+{
+    /// Create a new `ClosedLoop` motor
+    ///
+    /// The motor uses [RpmController::default] with its feed-forward scaled
+    /// to `max_rpm`, a tolerance of 2% of `max_rpm`, a 10 ms control period
+    /// and a two second settle timeout.
+    pub fn new(
+        motor: M,
+        tachometer: Tachometer<C>,
+        max_rpm: f32,
+    ) -> Self
+    {
+        let max_rpm = max_rpm.max(1.0);
+
+        Self {
+            motor,
+            tachometer: RefCell::new(tachometer),
+            controller: RpmController {
+                feed_forward: 100.0 / max_rpm,
+                ..RpmController::default()
+            },
+            max_rpm,
+            tolerance: max_rpm * 0.02,
+            period: Duration::from_millis(10),
+            settle_timeout: Duration::from_secs(2),
+            target: 0.0,
+            cruise: 100,
+            last_step: None,
+        }
+    }
+
+    /// Set the speed controller
+    #[must_use]
+    pub fn with_controller(
+        mut self,
+        controller: RpmController,
+    ) -> Self
+    {
+        self.controller = controller;
+        self
+    }
+
+    /// Set how close, in RPM, the motor must be to count as at speed
+    #[must_use]
+    pub fn with_tolerance(
+        mut self,
+        tolerance: f32,
+    ) -> Self
+    {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set how often the controller runs
+    #[must_use]
+    pub fn with_period(
+        mut self,
+        period: Duration,
+    ) -> Self
+    {
+        self.period = period;
+        self
+    }
+
+    /// Set how long `On` and `Speed` wait for the motor to settle
+    #[must_use]
+    pub fn with_settle_timeout(
+        mut self,
+        settle_timeout: Duration,
+    ) -> Self
+    {
+        self.settle_timeout = settle_timeout;
+        self
+    }
+
+    /// The target speed, in RPM
+    pub fn target(&self) -> f32 { self.target }
+
+    /// Whether the motor is running within tolerance of a non-zero target
+    pub fn at_speed(&self) -> bool
+    {
+        self.target > 0.0 && (self.measure() - self.target).abs() <= self.tolerance
+    }
+
+    /// Regulate the motor to the given RPM until it settles
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, M::Error>` - Whether the motor settled before the settle
+    ///   timeout, or an error if the motor could not be driven.
+    pub async fn spin_to(
+        &mut self,
+        rpm: f32,
+    ) -> Result<bool, M::Error>
+    {
+        self.target = rpm.clamp(0.0, self.max_rpm);
+
+        let start = Instant::now();
+        let mut ticker = Ticker::every(self.period);
+
+        while start.elapsed() < self.settle_timeout {
+            self.step().await?;
+
+            if self.at_speed() {
+                return Ok(true);
+            }
+
+            ticker.next().await;
+        }
+        Ok(false)
+    }
+
+    /// Regulate the motor at the current target for the given time
+    pub async fn regulate_for(
+        &mut self,
+        duration: Duration,
+    ) -> Result<(), M::Error>
+    {
+        let start = Instant::now();
+        let mut ticker = Ticker::every(self.period);
+
+        while start.elapsed() < duration {
+            self.step().await?;
+            ticker.next().await;
+        }
+        Ok(())
+    }
+
+    /// Run a single control step
+    ///
+    /// The controller is given the time actually measured since the previous
+    /// step, capped at a few periods so that a gap in regulation cannot wind
+    /// up the integral.
+    async fn step(&mut self) -> Result<(), M::Error>
+    {
+        let now = Instant::now();
+        let elapsed = self
+            .last_step
+            .replace(now)
+            .map_or(self.period, |last| now.saturating_duration_since(last))
+            .min(self.period * 4);
+        let rpm = self.tachometer.get_mut().sample_at(now);
+        let speed =
+            self.controller
+                .update(self.target, rpm, elapsed.as_micros() as f32 / 1_000_000.0);
+
+        self.motor.set_speed((speed + 0.5) as u8).await
+    }
+
+    /// The measured speed, in RPM, sampling the tachometer if it is due
+    fn measure(&self) -> f32 { self.tachometer.borrow_mut().refresh(self.period) }
+
+    /// Stop regulating, such as once the motor has been stopped
+    fn halt(&mut self)
+    {
+        self.target = 0.0;
+        self.last_step = None;
+        self.controller.reset();
+    }
+
+    /// The target, in RPM, for a speed in percent of `max_rpm`
+    fn rpm_for(
+        &self,
+        percent: u8,
+    ) -> f32
+    {
+        self.max_rpm * f32::from(percent.min(100)) / 100.0
+    }
+}
+
+impl<M: Motor, C: PulseCounter> Motor for ClosedLoop<M, C>
+{
+    type Error = M::Error;
+
+    /// Turn the inner motor on, open-loop
+    ///
+    /// Regulation needs to run over time, so it only takes over once the
+    /// motor is supervised, or through `MotorCommand::On` and the other async
+    /// operations.
+    fn on(&mut self) -> Result<(), Self::Error>
+    {
+        self.target = self.rpm_for(self.cruise);
+        self.motor.on()
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error>
+    {
+        self.halt();
+        self.motor.off()
+    }
+
+    fn brake(&mut self) -> Result<(), Self::Error>
+    {
+        self.halt();
+        self.motor.brake()
+    }
+
+    /// Spin up to the cruise speed, under regulation
+    async fn start(&mut self) -> Result<(), Self::Error> { self.set_speed(self.cruise).await }
+
+    async fn launch(
+        &mut self,
+        profile: LaunchProfile,
+    ) -> Result<(), Self::Error>
+    {
+        self.spin_to(self.rpm_for(self.cruise)).await?;
+
+        if profile.spin_up > Duration::from_ticks(0) {
+            self.regulate_for(profile.spin_up).await?;
+        }
+
+        for pulse in 0..profile.pulses {
+            self.target = self.rpm_for(self.cruise);
+            self.regulate_for(profile.on_time(pulse)).await?;
+            self.off()?;
+            Timer::after(profile.off).await;
+        }
+        Ok(())
+    }
+
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        if percent == 0 {
+            return self.off();
+        }
+
+        self.cruise = percent.min(100);
+        self.spin_to(self.rpm_for(percent)).await?;
+        Ok(())
+    }
+
+    fn rpm(&self) -> Option<f32> { Some(self.measure()) }
+
+    fn current(&self) -> Option<f32> { self.motor.current() }
+
+    fn thermal_budget(&self) -> Option<f32> { self.motor.thermal_budget() }
+
+    fn take_fault(&mut self) -> Option<MotorFault> { self.motor.take_fault() }
+
+    /// Keep regulating, or at least sampling, until the inner motor is cut
+    async fn supervise(&mut self) -> Result<(), Self::Error>
+    {
+        let mut ticker = Ticker::every(self.period);
+
+        let result = loop {
+            match select(self.motor.supervise(), ticker.next()).await {
+                Either::First(result) => break result,
+                Either::Second(()) if self.target > 0.0 => {
+                    if let Err(error) = self.step().await {
+                        break Err(error);
+                    }
+                }
+                Either::Second(()) => {
+                    self.measure();
+                }
+            }
+        };
+
+        self.halt();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::motor::{FlywheelModel, ModelCounter, ModelMotor};
+
+    const MAX_RPM: f32 = 10_000.0;
+
+    fn model() -> FlywheelModel { FlywheelModel::new(MAX_RPM, 0.05).with_pulses_per_revolution(12) }
+
+    fn closed_loop(model: &FlywheelModel) -> ClosedLoop<ModelMotor<'_>, ModelCounter<'_>>
+    {
+        ClosedLoop::new(model.motor(), Tachometer::new(model.counter(), 12), MAX_RPM)
+    }
+
+    #[test]
+    fn controller_holds_model_at_target()
+    {
+        let model = model();
+        // feed-forward well short of the model, so the integral has to make up
+        // the difference
+        let mut controller = RpmController::new(0.005, 0.02, 50.0 / MAX_RPM);
+
+        for _ in 0..2_000 {
+            model.set_speed(controller.update(6_000.0, model.rpm(), 0.01));
+            model.advance(0.01);
+        }
+
+        assert!((model.rpm() - 6_000.0).abs() < 100.0, "{} RPM", model.rpm());
+    }
+
+    #[test]
+    fn controller_does_not_wind_up_when_saturated()
+    {
+        let model = model();
+        let mut controller = RpmController::new(0.005, 0.02, 100.0 / MAX_RPM);
+
+        // a target the flywheel can never reach saturates the output
+        for _ in 0..500 {
+            model.set_speed(controller.update(20_000.0, model.rpm(), 0.01));
+            model.advance(0.01);
+        }
+
+        // so a reachable one is met without first overshooting for seconds
+        let mut peak = 0.0_f32;
+
+        for _ in 0..200 {
+            model.set_speed(controller.update(5_000.0, model.rpm(), 0.01));
+            model.advance(0.01);
+            peak = peak.max(model.rpm());
+        }
+
+        assert!(
+            peak > 5_000.0 && model.rpm() < 5_100.0,
+            "{} RPM",
+            model.rpm()
+        );
+    }
+
+    #[test]
+    fn spin_to_settles_at_target()
+    {
+        let model = model();
+        let mut motor = closed_loop(&model);
+
+        assert_eq!(block_on(motor.spin_to(6_000.0)), Ok(true));
+        assert!(motor.at_speed());
+        assert!((model.rpm() - 6_000.0).abs() < 300.0, "{} RPM", model.rpm());
+    }
+
+    #[test]
+    fn rpm_follows_the_flywheel_between_commands()
+    {
+        let model = model();
+        let mut motor = closed_loop(&model);
+
+        block_on(motor.spin_to(6_000.0)).unwrap();
+        motor.off().unwrap();
+        block_on(Timer::after_millis(400));
+
+        // polled, as fire control does while waiting for the flywheels, with
+        // no command or supervision in between
+        let mut rpm = motor.rpm().unwrap();
+
+        for _ in 0..5 {
+            block_on(Timer::after_millis(20));
+            rpm = motor.rpm().unwrap();
+        }
+
+        assert!(rpm < 600.0, "{rpm} RPM");
+    }
+
+    #[test]
+    fn supervise_keeps_regulating_between_commands()
+    {
+        let model = model();
+        let mut motor = closed_loop(&model);
+
+        block_on(motor.set_speed(50)).unwrap();
+        motor.off().unwrap();
+
+        // `on` drives the inner motor flat out, open-loop
+        motor.on().unwrap();
+        block_on(select(motor.supervise(), Timer::after_millis(600)));
+
+        assert_eq!(motor.target(), 5_000.0);
+        assert!((model.rpm() - 5_000.0).abs() < 300.0, "{} RPM", model.rpm());
+    }
+}
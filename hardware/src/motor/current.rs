@@ -0,0 +1,132 @@
+//! ## Current Sensing
+//!
+//! Reads the current drawn by a motor, such as from a shunt resistor or a
+//! hall-effect sensor on an ADC channel. Readings are taken through a closure,
+//! so that no particular ADC driver is assumed.
+
+/// Current Sensor
+///
+/// Something that can measure the current drawn by a motor. Closures
+/// returning the current in amps can be used directly, which keeps the
+/// sensor independent of any particular ADC driver.
+///
+/// - Ex: `|| adc.read_oneshot(&mut pin).ok().map(|raw| f32::from(raw) *
+///   AMPS_PER_COUNT)`
+pub trait CurrentSensor
+{
+    /// The current drawn by the motor
+    ///
+    /// # Returns
+    ///
+    /// * `Option<f32>` - The current, in amps, or `None` if it could not be
+    ///   read.
+    fn amps(&mut self) -> Option<f32>;
+}
+
+impl<F: FnMut() -> Option<f32>> CurrentSensor for F
+{
+    fn amps(&mut self) -> Option<f32> { self() }
+}
+
+/// ADC Current Sensor
+///
+/// Converts raw readings of an ADC channel, from a current-sense amplifier or
+/// a hall-effect sensor, into amps.
+///
+/// # Type Parameters
+/// - `R`: Reads the ADC channel, returning the raw count, or `None` if the
+///   conversion failed.
+///
+/// # Fields
+/// - `read`: Reads the ADC channel.
+/// - `zero`: The raw count at zero current.
+/// - `amps_per_count`: The current, in amps, of a single count.
+pub struct AdcCurrent<R: FnMut() -> Option<u16>>
+{
+    read: R,
+    zero: u16,
+    amps_per_count: f32,
+}
+
+impl<R: FnMut() -> Option<u16>> AdcCurrent<R>
+{
+    /// Create a new `AdcCurrent` sensor from the supplied ADC reading
+    pub fn new(
+        read: R,
+        zero: u16,
+        amps_per_count: f32,
+    ) -> Self
+    {
+        Self {
+            read,
+            zero,
+            amps_per_count,
+        }
+    }
+
+    /// Create a new `AdcCurrent` sensor for a shunt resistor and amplifier
+    ///
+    /// # Parameters
+    ///
+    /// * `read` - Reads the ADC channel.
+    /// * `full_scale` - The voltage at the ADC's full-scale reading.
+    /// * `bits` - The ADC's resolution, from 1 to 16 bits; anything outside
+    ///   that range is clamped to it, since readings are 16-bit.
+    /// * `shunt_ohms` - The shunt resistance, in ohms.
+    /// * `gain` - The amplifier gain.
+    pub fn shunt(
+        read: R,
+        full_scale: f32,
+        bits: u8,
+        shunt_ohms: f32,
+        gain: f32,
+    ) -> Self
+    {
+        let volts_per_count = full_scale / ((1_u32 << bits.clamp(1, 16)) - 1) as f32;
+
+        Self::new(read, 0, volts_per_count / (shunt_ohms * gain))
+    }
+}
+
+impl<R: FnMut() -> Option<u16>> CurrentSensor for AdcCurrent<R>
+{
+    fn amps(&mut self) -> Option<f32>
+    {
+        let raw = (self.read)()?;
+
+        Some((f32::from(raw) - f32::from(self.zero)) * self.amps_per_count)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn converts_counts_to_amps()
+    {
+        let mut sensor = AdcCurrent::new(|| Some(2_148), 2_048, 0.01);
+
+        assert_eq!(sensor.amps(), Some(1.0));
+    }
+
+    #[test]
+    fn shunt_scales_to_full_scale()
+    {
+        // 3.3 V full scale across a 10 mΩ shunt at a gain of 50
+        let mut sensor = AdcCurrent::shunt(|| Some(4_095), 3.3, 12, 0.01, 50.0);
+
+        assert!((sensor.amps().unwrap() - 6.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shunt_clamps_resolution()
+    {
+        let mut wide = AdcCurrent::shunt(|| Some(u16::MAX), 3.3, 32, 0.01, 50.0);
+        let mut zero = AdcCurrent::shunt(|| Some(1), 3.3, 0, 0.01, 50.0);
+
+        assert!((wide.amps().unwrap() - 6.6).abs() < 1e-4);
+        assert!((zero.amps().unwrap() - 6.6).abs() < 1e-4);
+    }
+}
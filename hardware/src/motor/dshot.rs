@@ -0,0 +1,252 @@
+//! ## DShot Encoding
+//!
+//! Pure frame encoding for the DShot digital ESC protocol. Nothing in this
+//! module touches hardware, so it can be exercised on the host; boards supply
+//! a [DshotOutput] that puts the encoded frames on the wire.
+//!
+//! A frame is 16 bits, sent most significant bit first:
+//!
+//! * 11 bits of value: `0` disarms, `1..=47` are special commands and
+//!   `48..=2047` are throttle.
+//! * 1 telemetry-request bit.
+//! * 4 bits of CRC over the preceding 12 bits.
+
+use core::fmt;
+
+/// DShot Speed
+///
+/// Variants:
+/// - `Dshot150`: 150 kbit/s.
+/// - `Dshot300`: 300 kbit/s.
+/// - `Dshot600`: 600 kbit/s.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DshotSpeed
+{
+    Dshot150,
+    Dshot300,
+    Dshot600,
+}
+
+impl DshotSpeed
+{
+    /// Bit rate, in kbit/s
+    pub const fn kbps(self) -> u32
+    {
+        match self {
+            DshotSpeed::Dshot150 => 150,
+            DshotSpeed::Dshot300 => 300,
+            DshotSpeed::Dshot600 => 600,
+        }
+    }
+
+    /// Length of a single bit, in nanoseconds
+    pub const fn bit_ns(self) -> u32 { 1_000_000 / self.kbps() }
+
+    /// How long the line is held high for the given bit, in nanoseconds
+    ///
+    /// A `1` is high for three quarters of the bit, a `0` for three eighths.
+    pub const fn high_ns(
+        self,
+        bit: bool,
+    ) -> u32
+    {
+        match bit {
+            true => self.bit_ns() * 3 / 4,
+            false => self.bit_ns() * 3 / 8,
+        }
+    }
+}
+
+/// DShot Frame
+///
+/// A single encoded 16-bit frame.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub struct DshotFrame(u16);
+
+impl DshotFrame
+{
+    /// Lowest value that encodes a throttle rather than a special command
+    pub const THROTTLE_MIN: u16 = 48;
+
+    /// Highest value that fits in a frame
+    pub const THROTTLE_MAX: u16 = 2047;
+
+    /// Encode a raw 11-bit value, with or without a telemetry request
+    ///
+    /// Values above [DshotFrame::THROTTLE_MAX] are clamped.
+    pub const fn new(
+        value: u16,
+        telemetry: bool,
+    ) -> Self
+    {
+        let value = if value > Self::THROTTLE_MAX {
+            Self::THROTTLE_MAX
+        }
+        else {
+            value
+        };
+
+        let packet = (value << 1) | telemetry as u16;
+        let crc = (packet ^ (packet >> 4) ^ (packet >> 8)) & 0x0F;
+
+        Self((packet << 4) | crc)
+    }
+
+    /// Encode a throttle given in per-mille of full scale
+    ///
+    /// `0` encodes the disarm/stop value; anything else is mapped onto the
+    /// throttle range `48..=2047`.
+    pub const fn throttle(
+        permille: u16,
+        telemetry: bool,
+    ) -> Self
+    {
+        let permille = if permille > 1000 { 1000 } else { permille };
+
+        match permille {
+            0 => Self::new(0, telemetry),
+            _ => {
+                let span = (Self::THROTTLE_MAX - Self::THROTTLE_MIN) as u32;
+                let value = Self::THROTTLE_MIN as u32 + span * permille as u32 / 1000;
+
+                Self::new(value as u16, telemetry)
+            }
+        }
+    }
+
+    /// The encoded frame, as sent on the wire
+    pub const fn bits(self) -> u16 { self.0 }
+
+    /// The 11-bit value carried by the frame
+    pub const fn value(self) -> u16 { self.0 >> 5 }
+
+    /// Whether the frame requests telemetry
+    pub const fn telemetry(self) -> bool { self.0 & 0x10 != 0 }
+
+    /// The frame's 4-bit CRC
+    pub const fn crc(self) -> u8 { (self.0 & 0x0F) as u8 }
+
+    /// Duty cycles for sending the frame one PWM period per bit
+    ///
+    /// Suitable for feeding a PWM channel from DMA, with the PWM period set to
+    /// [DshotSpeed::bit_ns] and `max_duty` being the channel's
+    /// `max_duty_cycle()`. Bits are in transmission order.
+    pub fn duty_cycles(
+        self,
+        max_duty: u16,
+    ) -> [u16; 16]
+    {
+        let mut duties = [0; 16];
+
+        for (index, duty) in duties.iter_mut().enumerate() {
+            let bit = self.0 & (0x8000 >> index) != 0;
+            let eighths = if bit { 6 } else { 3 };
+
+            *duty = (u32::from(max_duty) * eighths / 8) as u16;
+        }
+
+        duties
+    }
+}
+
+/// DShot Output
+///
+/// Puts DShot frames on the wire, typically through a peripheral such as the
+/// ESP32 RMT or an RP2040 PIO state machine.
+///
+/// ESCs disarm when the signal stops, so implementations are expected to
+/// keep repeating the most recent frame until a new one is sent.
+pub trait DshotOutput
+{
+    type Error: fmt::Debug;
+
+    /// Start sending the given frame, replacing the previous one
+    fn send(
+        &mut self,
+        frame: DshotFrame,
+    ) -> Result<(), Self::Error>;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn encodes_known_frame()
+    {
+        // the worked example from the DShot protocol description
+        let frame = DshotFrame::new(1046, false);
+
+        assert_eq!(frame.bits(), 0b1000_0010_1100_0110);
+        assert_eq!(frame.value(), 1046);
+        assert!(!frame.telemetry());
+        assert_eq!(frame.crc(), 0b0110);
+    }
+
+    #[test]
+    fn crc_covers_value_and_telemetry_bit()
+    {
+        for value in 0..=DshotFrame::THROTTLE_MAX {
+            for telemetry in [false, true] {
+                let frame = DshotFrame::new(value, telemetry);
+                let bits = frame.bits();
+                let crc = (bits >> 4 ^ bits >> 8 ^ bits >> 12) & 0x0F;
+
+                assert_eq!(frame.value(), value);
+                assert_eq!(frame.telemetry(), telemetry);
+                assert_eq!(u16::from(frame.crc()), crc);
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_values_out_of_range()
+    {
+        assert_eq!(
+            DshotFrame::new(u16::MAX, false).value(),
+            DshotFrame::THROTTLE_MAX
+        );
+    }
+
+    #[test]
+    fn maps_throttle_onto_range()
+    {
+        assert_eq!(DshotFrame::throttle(0, false).value(), 0);
+        assert_eq!(
+            DshotFrame::throttle(1, false).value(),
+            DshotFrame::THROTTLE_MIN + 1
+        );
+        assert_eq!(DshotFrame::throttle(500, false).value(), 1047);
+        assert_eq!(
+            DshotFrame::throttle(1000, false).value(),
+            DshotFrame::THROTTLE_MAX
+        );
+        assert_eq!(
+            DshotFrame::throttle(1500, true),
+            DshotFrame::throttle(1000, true)
+        );
+    }
+
+    #[test]
+    fn duty_cycles_follow_bits()
+    {
+        let frame = DshotFrame::new(1046, true);
+        let duties = frame.duty_cycles(800);
+
+        for (index, duty) in duties.into_iter().enumerate() {
+            let bit = frame.bits() & (0x8000 >> index) != 0;
+
+            assert_eq!(duty, if bit { 600 } else { 300 });
+        }
+    }
+
+    #[test]
+    fn bit_timing()
+    {
+        assert_eq!(DshotSpeed::Dshot600.bit_ns(), 1666);
+        assert_eq!(DshotSpeed::Dshot600.high_ns(true), 1249);
+        assert_eq!(DshotSpeed::Dshot600.high_ns(false), 624);
+        assert_eq!(DshotSpeed::Dshot150.bit_ns(), 6666);
+    }
+}
@@ -0,0 +1,295 @@
+//! ## ESC Motor
+//!
+//! Drives brushless flywheels through hobby ESCs, either with standard
+//! servo-style PWM or with the DShot digital protocol.
+
+use core::fmt;
+
+use embassy_time::{Duration, Timer};
+use embedded_hal::pwm::SetDutyCycle;
+
+use super::{
+    dshot::{DshotFrame, DshotOutput},
+    LaunchProfile,
+    Motor,
+};
+
+/// ESC Signal
+///
+/// The protocol used to send a throttle to an ESC.
+pub trait EscSignal
+{
+    type Error: fmt::Debug;
+
+    /// Send the given throttle, in per-mille of full scale
+    ///
+    /// A throttle of `0` is the ESC's idle/stop signal, which is also what
+    /// ESCs expect to see while arming.
+    fn throttle(
+        &mut self,
+        permille: u16,
+    ) -> Result<(), Self::Error>;
+}
+
+/// Servo PWM Signal
+///
+/// Standard 50 Hz servo-style throttle, where the pulse width runs from
+/// `min_pulse_us` at zero throttle to `max_pulse_us` at full throttle. A
+/// range with `max_pulse_us` below `min_pulse_us` holds the pulse at
+/// `min_pulse_us`, rather than wrapping around.
+///
+/// # Type Parameters
+/// - `P`: The PWM channel connected to the ESC's signal wire.
+///
+/// # Fields
+/// - `pwm`: The PWM channel connected to the ESC's signal wire.
+/// - `min_pulse_us`: Pulse width, in microseconds, at zero throttle.
+/// - `max_pulse_us`: Pulse width, in microseconds, at full throttle.
+/// - `period_us`: The PWM period, in microseconds.
+pub struct ServoPwmSignal<P: SetDutyCycle>
+{
+    pwm: P,
+    pub min_pulse_us: u16,
+    pub max_pulse_us: u16,
+    pub period_us: u32,
+}
+
+impl<P: SetDutyCycle> ServoPwmSignal<P>
+{
+    /// Create a new `ServoPwmSignal` with the standard 1000-2000 µs range at
+    /// 50 Hz
+    pub fn new(pwm: P) -> Self
+    {
+        Self {
+            pwm,
+            min_pulse_us: 1000,
+            max_pulse_us: 2000,
+            period_us: 20_000,
+        }
+    }
+}
+
+impl<P: SetDutyCycle> EscSignal for ServoPwmSignal<P>
+{
+    type Error = P::Error;
+
+    fn throttle(
+        &mut self,
+        permille: u16,
+    ) -> Result<(), Self::Error>
+    {
+        let span = u32::from(self.max_pulse_us.saturating_sub(self.min_pulse_us));
+        let pulse = u32::from(self.min_pulse_us) + span * u32::from(permille.min(1000)) / 1000;
+        let duty = u32::from(self.pwm.max_duty_cycle()) * pulse / self.period_us.max(1);
+
+        self.pwm.set_duty_cycle(duty as u16)
+    }
+}
+
+/// DShot Signal
+///
+/// DShot digital throttle, sent through a board-specific [DshotOutput].
+///
+/// # Type Parameters
+/// - `O`: The output that puts frames on the wire.
+///
+/// # Fields
+/// - `output`: The output that puts frames on the wire.
+/// - `telemetry`: Whether every frame requests telemetry from the ESC.
+pub struct DshotSignal<O: DshotOutput>
+{
+    output: O,
+    pub telemetry: bool,
+}
+
+impl<O: DshotOutput> DshotSignal<O>
+{
+    /// Create a new `DshotSignal` that does not request telemetry
+    pub fn new(output: O) -> Self
+    {
+        Self {
+            output,
+            telemetry: false,
+        }
+    }
+}
+
+impl<O: DshotOutput> EscSignal for DshotSignal<O>
+{
+    type Error = O::Error;
+
+    fn throttle(
+        &mut self,
+        permille: u16,
+    ) -> Result<(), Self::Error>
+    {
+        self.output
+            .send(DshotFrame::throttle(permille, self.telemetry))
+    }
+}
+
+/// ESC Error
+#[derive(Debug)]
+pub enum EscError<E>
+{
+    Signal(E),
+    NotArmed,
+}
+
+/// ESC Motor
+///
+/// A brushless motor behind a hobby ESC. The ESC must be armed, by holding
+/// zero throttle for a while, before it accepts any other throttle.
+/// `MotorCommand`s arm the ESC automatically the first time they need it;
+/// the synchronous [Motor::on] refuses to run until it has been armed.
+///
+/// # Type Parameters
+/// - `S`: The throttle protocol.
+///
+/// # Fields
+/// - `signal`: The throttle protocol.
+/// - `arming`: How long zero throttle is held to arm the ESC.
+/// - `armed`: Whether the arming sequence has completed.
+/// - `cruise`: The speed `On` runs at; the last non-zero commanded speed.
+pub struct Esc<S: EscSignal>
+{
+    signal: S,
+    arming: Duration,
+    armed: bool,
+    cruise: u8,
+}
+
+impl<S: EscSignal> Esc<S>
+{
+    /// Create a new, unarmed `Esc` with a two second arming time
+    pub fn new(signal: S) -> Self
+    {
+        Self {
+            signal,
+            arming: Duration::from_secs(2),
+            armed: false,
+            cruise: 100,
+        }
+    }
+
+    /// Set how long zero throttle is held to arm the ESC
+    #[must_use]
+    pub fn with_arming_time(
+        mut self,
+        arming: Duration,
+    ) -> Self
+    {
+        self.arming = arming;
+        self
+    }
+
+    /// Whether the arming sequence has completed
+    pub fn is_armed(&self) -> bool { self.armed }
+
+    /// Run the arming sequence
+    ///
+    /// Holds zero throttle for the arming time, after which the ESC accepts
+    /// throttle commands.
+    pub async fn arm(&mut self) -> Result<(), EscError<S::Error>>
+    {
+        self.write(0)?;
+        Timer::after(self.arming).await;
+        self.armed = true;
+        Ok(())
+    }
+
+    /// Arm the ESC, unless it is already armed
+    async fn ensure_armed(&mut self) -> Result<(), EscError<S::Error>>
+    {
+        match self.armed {
+            true => Ok(()),
+            false => self.arm().await,
+        }
+    }
+
+    fn write(
+        &mut self,
+        permille: u16,
+    ) -> Result<(), EscError<S::Error>>
+    {
+        self.signal.throttle(permille).map_err(EscError::Signal)
+    }
+}
+
+impl<P: SetDutyCycle> Esc<ServoPwmSignal<P>>
+{
+    /// Run the ESC's throttle range calibration
+    ///
+    /// Sends full throttle for `hold`, which the ESC records as the top of
+    /// its range, then zero throttle for `hold`, which it records as the
+    /// bottom. The ESC must be powered up during the first phase, so start
+    /// this before (or immediately after) applying power. The ESC is armed
+    /// once calibration completes.
+    pub async fn calibrate(
+        &mut self,
+        hold: Duration,
+    ) -> Result<(), EscError<P::Error>>
+    {
+        self.armed = false;
+
+        self.write(1000)?;
+        Timer::after(hold).await;
+        self.write(0)?;
+        Timer::after(hold).await;
+
+        self.armed = true;
+        Ok(())
+    }
+}
+
+impl<S: EscSignal> Motor for Esc<S>
+{
+    type Error = EscError<S::Error>;
+
+    fn on(&mut self) -> Result<(), Self::Error>
+    {
+        if !self.armed {
+            return Err(EscError::NotArmed);
+        }
+
+        self.write(u16::from(self.cruise) * 10)
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error> { self.write(0) }
+
+    /// Arm the ESC if needed, then run at the cruise speed
+    async fn start(&mut self) -> Result<(), Self::Error>
+    {
+        self.ensure_armed().await?;
+        self.on()
+    }
+
+    /// Arm the ESC if needed, then run the launch profile
+    ///
+    /// Arming comes first even without a spin-up, as the pulses are driven
+    /// with `on`, which refuses to run an unarmed ESC.
+    async fn launch(
+        &mut self,
+        profile: LaunchProfile,
+    ) -> Result<(), Self::Error>
+    {
+        self.ensure_armed().await?;
+        profile.run(self).await
+    }
+
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        let percent = percent.min(100);
+
+        if percent == 0 {
+            return self.off();
+        }
+
+        self.ensure_armed().await?;
+        self.cruise = percent;
+        self.on()
+    }
+}
@@ -0,0 +1,332 @@
+//! ## H-Bridge Motor
+//!
+//! Bidirectional control for brushed motors behind an H-bridge driver such as
+//! the DRV8833, L298N or TB6612, with active braking and coasting.
+
+use core::fmt;
+
+use embassy_time::{Duration, Timer};
+use embedded_hal::{digital::OutputPin, pwm::SetDutyCycle};
+
+use super::Motor;
+
+/// Direction
+///
+/// Variants:
+/// - `Forward`: The motor's forward direction.
+/// - `Reverse`: The motor's reverse direction.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Direction
+{
+    Forward,
+    Reverse,
+}
+
+/// H-Bridge Trait
+///
+/// The wiring between the microcontroller and an H-bridge driver.
+pub trait HBridge
+{
+    type Error: fmt::Debug;
+
+    /// Drive the motor in the given direction
+    ///
+    /// # Parameters
+    ///
+    /// * `direction` - The direction to turn the motor.
+    /// * `percent` - The duty cycle, from 0 to 100 percent.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the bridge is
+    ///   successfully driven, or an error of type `Self::Error` if the
+    ///   operation fails.
+    fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>;
+
+    /// Short the motor's terminals, actively braking it
+    fn brake(&mut self) -> Result<(), Self::Error>;
+
+    /// Leave the motor's terminals floating, letting it spin down freely
+    fn coast(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Duty cycle for the given percentage of the channel's range
+fn duty<P: SetDutyCycle>(
+    pwm: &P,
+    percent: u8,
+) -> u16
+{
+    (u32::from(pwm.max_duty_cycle()) * u32::from(percent.min(100)) / 100) as u16
+}
+
+/// Dual PWM Bridge
+///
+/// IN1/IN2 wiring, with a PWM channel on each input, as used by the DRV8833.
+/// The L298N and TB6612 can be wired the same way by tying their enable
+/// (`ENA`/`PWMA`) pin high.
+///
+/// | IN1  | IN2  | Result  |
+/// |------|------|---------|
+/// | PWM  | low  | Forward |
+/// | low  | PWM  | Reverse |
+/// | high | high | Brake   |
+/// | low  | low  | Coast   |
+///
+/// # Type Parameters
+/// - `A`: The PWM channel on IN1.
+/// - `B`: The PWM channel on IN2.
+pub struct DualPwmBridge<A: SetDutyCycle, B: SetDutyCycle<Error = A::Error>>
+{
+    in1: A,
+    in2: B,
+}
+
+impl<A: SetDutyCycle, B: SetDutyCycle<Error = A::Error>> DualPwmBridge<A, B>
+{
+    /// Create a new `DualPwmBridge` from the channels on IN1 and IN2
+    pub fn new(
+        in1: A,
+        in2: B,
+    ) -> Self
+    {
+        Self { in1, in2 }
+    }
+}
+
+impl<A: SetDutyCycle, B: SetDutyCycle<Error = A::Error>> HBridge for DualPwmBridge<A, B>
+{
+    type Error = A::Error;
+
+    fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        match direction {
+            Direction::Forward => {
+                self.in2.set_duty_cycle_fully_off()?;
+                self.in1.set_duty_cycle(duty(&self.in1, percent))
+            }
+            Direction::Reverse => {
+                self.in1.set_duty_cycle_fully_off()?;
+                self.in2.set_duty_cycle(duty(&self.in2, percent))
+            }
+        }
+    }
+
+    fn brake(&mut self) -> Result<(), Self::Error>
+    {
+        self.in1.set_duty_cycle_fully_on()?;
+        self.in2.set_duty_cycle_fully_on()
+    }
+
+    fn coast(&mut self) -> Result<(), Self::Error>
+    {
+        self.in1.set_duty_cycle_fully_off()?;
+        self.in2.set_duty_cycle_fully_off()
+    }
+}
+
+/// PWM/DIR Bridge Error
+#[derive(Debug)]
+pub enum PwmDirError<P, D>
+{
+    PwmError(P),
+    DirError(D),
+}
+
+/// PWM/DIR Bridge
+///
+/// PWM and direction wiring, as used by phase/enable drivers such as the
+/// DRV8838: the direction pin selects which way the motor turns and the PWM
+/// channel sets its speed.
+///
+/// This wiring has no separate coast state. Both [HBridge::brake] and
+/// [HBridge::coast] turn the PWM channel fully off, and whether the motor
+/// then brakes or coasts depends on the driver.
+///
+/// # Type Parameters
+/// - `P`: The PWM channel on the enable/PWM input.
+/// - `D`: The output pin on the phase/direction input.
+pub struct PwmDirBridge<P: SetDutyCycle, D: OutputPin>
+{
+    pwm: P,
+    dir: D,
+}
+
+impl<P: SetDutyCycle, D: OutputPin> PwmDirBridge<P, D>
+{
+    /// Create a new `PwmDirBridge` from the PWM channel and direction pin
+    pub fn new(
+        pwm: P,
+        dir: D,
+    ) -> Self
+    {
+        Self { pwm, dir }
+    }
+}
+
+impl<P: SetDutyCycle, D: OutputPin> HBridge for PwmDirBridge<P, D>
+{
+    type Error = PwmDirError<P::Error, D::Error>;
+
+    fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        match direction {
+            Direction::Forward => self.dir.set_high(),
+            Direction::Reverse => self.dir.set_low(),
+        }
+        .map_err(PwmDirError::DirError)?;
+
+        self.pwm
+            .set_duty_cycle(duty(&self.pwm, percent))
+            .map_err(PwmDirError::PwmError)
+    }
+
+    fn brake(&mut self) -> Result<(), Self::Error>
+    {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(PwmDirError::PwmError)
+    }
+
+    fn coast(&mut self) -> Result<(), Self::Error> { self.brake() }
+}
+
+/// H-Bridge Motor
+///
+/// A brushed motor that can be driven in either direction, braked or left to
+/// coast. [Motor::off] coasts; `MotorCommand::Brake` stops the motor
+/// actively.
+///
+/// Reversing a spinning motor straight away causes a large current spike, so
+/// a change of direction while the motor is running brakes for the reversal
+/// time first.
+///
+/// # Type Parameters
+/// - `B`: The H-bridge wiring.
+///
+/// # Fields
+/// - `bridge`: The H-bridge wiring.
+/// - `reversal`: How long the motor is braked before changing direction.
+/// - `direction`: The direction the motor turns, or last turned.
+/// - `speed`: The speed, in percent, currently driven; `0` when stopped.
+/// - `cruise`: The speed `On` runs at; the last non-zero commanded speed.
+pub struct HBridgeMotor<B: HBridge>
+{
+    bridge: B,
+    reversal: Duration,
+    direction: Direction,
+    speed: u8,
+    cruise: u8,
+}
+
+impl<B: HBridge> HBridgeMotor<B>
+{
+    /// Create a new `HBridgeMotor` from the supplied bridge
+    ///
+    /// The motor starts stopped and facing forward, with a cruise speed of
+    /// 100% and a 100 ms reversal time.
+    pub fn new(bridge: B) -> Self
+    {
+        Self {
+            bridge,
+            reversal: Duration::from_millis(100),
+            direction: Direction::Forward,
+            speed: 0,
+            cruise: 100,
+        }
+    }
+
+    /// Set how long the motor is braked before changing direction
+    #[must_use]
+    pub fn with_reversal_time(
+        mut self,
+        reversal: Duration,
+    ) -> Self
+    {
+        self.reversal = reversal;
+        self
+    }
+
+    /// The direction the motor turns, or last turned
+    pub fn direction(&self) -> Direction { self.direction }
+
+    /// The speed, in percent, currently driven
+    pub fn speed(&self) -> u8 { self.speed }
+
+    /// Drive the bridge, recording the new state
+    fn write(
+        &mut self,
+        direction: Direction,
+        speed: u8,
+    ) -> Result<(), B::Error>
+    {
+        let speed = speed.min(100);
+
+        self.bridge.drive(direction, speed)?;
+        self.direction = direction;
+        self.speed = speed;
+        Ok(())
+    }
+}
+
+impl<B: HBridge> Motor for HBridgeMotor<B>
+{
+    type Error = B::Error;
+
+    /// Run at the cruise speed, in the current direction
+    fn on(&mut self) -> Result<(), Self::Error> { self.write(self.direction, self.cruise) }
+
+    /// Let the motor coast to a stop
+    fn off(&mut self) -> Result<(), Self::Error>
+    {
+        self.bridge.coast()?;
+        self.speed = 0;
+        Ok(())
+    }
+
+    fn brake(&mut self) -> Result<(), Self::Error>
+    {
+        self.bridge.brake()?;
+        self.speed = 0;
+        Ok(())
+    }
+
+    /// Set the speed, keeping the current direction
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        self.drive(self.direction, percent).await
+    }
+
+    async fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        if percent == 0 {
+            return self.off();
+        }
+
+        if direction != self.direction && self.speed > 0 {
+            self.brake()?;
+            Timer::after(self.reversal).await;
+        }
+
+        self.cruise = percent.min(100);
+        self.write(direction, percent)
+    }
+}
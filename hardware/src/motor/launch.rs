@@ -0,0 +1,133 @@
+//! ## Launch Profiles
+//!
+//! Describes the pulse sequence a motor runs when it is told to launch, and
+//! how a client can adjust it for a single launch over the wire.
+
+use core::fmt;
+
+use embassy_time::{Duration, Timer};
+
+use super::Motor;
+
+/// Launch Profile
+///
+/// The sequence a motor runs for `MotorCommand::Launch`: an optional
+/// spin-up, followed by a number of on/off pulses.
+///
+/// # Fields
+/// - `pulses`: Number of on/off pulses.
+/// - `on`: How long each pulse keeps the motor on.
+/// - `off`: How long the motor rests between pulses.
+/// - `spin_up`: How long the motor is held on before the first pulse.
+/// - `ramp`: Number of pulses over which the on time builds up to `on`, for a
+///   soft start. `0` runs every pulse at full length.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub struct LaunchProfile
+{
+    pub pulses: u16,
+    pub on: Duration,
+    pub off: Duration,
+    pub spin_up: Duration,
+    pub ramp: u16,
+}
+
+impl Default for LaunchProfile
+{
+    fn default() -> Self
+    {
+        Self {
+            pulses: 3,
+            on: Duration::from_millis(100),
+            off: Duration::from_millis(100),
+            spin_up: Duration::from_millis(0),
+            ramp: 0,
+        }
+    }
+}
+
+impl LaunchProfile
+{
+    /// How long the given pulse (counting from 0) keeps the motor on
+    ///
+    /// Pulses within the ramp are shortened linearly, so the first of `ramp`
+    /// pulses is on for `on / (ramp + 1)`.
+    pub fn on_time(
+        &self,
+        pulse: u16,
+    ) -> Duration
+    {
+        if pulse >= self.ramp {
+            return self.on;
+        }
+
+        self.on * (u32::from(pulse) + 1) / (u32::from(self.ramp) + 1)
+    }
+
+    /// Run the sequence on the given motor
+    ///
+    /// The spin-up starts the motor with [Motor::start], and each pulse turns
+    /// it on and off with [Motor::on] and [Motor::off]. Dropping the future
+    /// may leave the motor on.
+    pub async fn run<M: Motor + ?Sized>(
+        self,
+        motor: &mut M,
+    ) -> Result<(), M::Error>
+    {
+        if self.spin_up > Duration::from_ticks(0) {
+            motor.start().await?;
+            Timer::after(self.spin_up).await;
+        }
+
+        for pulse in 0..self.pulses {
+            motor.on()?;
+            Timer::after(self.on_time(pulse)).await;
+            motor.off()?;
+            Timer::after(self.off).await;
+        }
+        Ok(())
+    }
+
+    /// How long the whole sequence keeps the motor on, including the spin-up
+    pub fn on_total(&self) -> Duration
+    {
+        (0..self.pulses).fold(self.spin_up, |total, pulse| total + self.on_time(pulse))
+    }
+}
+
+/// Launch Overrides
+///
+/// Per-launch adjustments to a motor's [LaunchProfile], as sent over the
+/// wire. Any field left out keeps the motor's configured value.
+///
+/// - Ex: `{ "Motor": { "Launch": { "pulses": 3 } } }`
+/// - Ex: `{ "Motor": { "Launch": { "on_ms": 50, "off_ms": 150, "ramp": 2 } } }`
+#[derive(Copy, Clone, fmt::Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LaunchOverrides
+{
+    pub pulses: Option<u16>,
+    pub on_ms: Option<u32>,
+    pub off_ms: Option<u32>,
+    pub spin_up_ms: Option<u32>,
+    pub ramp: Option<u16>,
+}
+
+impl LaunchOverrides
+{
+    /// Apply the overrides on top of the given profile
+    pub fn apply(
+        &self,
+        profile: LaunchProfile,
+    ) -> LaunchProfile
+    {
+        let millis =
+            |ms: Option<u32>, default| ms.map_or(default, |ms| Duration::from_millis(ms.into()));
+
+        LaunchProfile {
+            pulses: self.pulses.unwrap_or(profile.pulses),
+            on: millis(self.on_ms, profile.on),
+            off: millis(self.off_ms, profile.off),
+            spin_up: millis(self.spin_up_ms, profile.spin_up),
+            ramp: self.ramp.unwrap_or(profile.ramp),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! ## Flywheel Model
+//!
+//! A simple simulated flywheel, so that speed control can be exercised on the
+//! host without hardware.
+
+use core::{cell::Cell, convert::Infallible};
+
+use embassy_time::Instant;
+
+use super::{Motor, PulseCounter};
+
+/// Flywheel Model
+///
+/// A first-order model of a motor and flywheel: at a given speed in percent,
+/// the flywheel approaches that fraction of `max_rpm` with the given time
+/// constant. It can be stepped directly with [FlywheelModel::advance], or
+/// driven in real time through [FlywheelModel::motor] and
+/// [FlywheelModel::counter], which share the model and advance it to
+/// [Instant::now] whenever they are used.
+///
+/// # Fields
+/// - `max_rpm`: The steady-state speed, in RPM, at 100%.
+/// - `time_constant`: Seconds to cover 63% of a change in speed.
+/// - `pulses_per_revolution`: Tachometer pulses produced per revolution.
+/// - `speed`: The speed, in percent, the motor is driven at.
+/// - `rpm`: The flywheel's current speed, in RPM.
+/// - `pulses`: Fractional tachometer pulses not yet counted.
+/// - `count`: Tachometer pulses counted so far.
+/// - `updated`: When the model was last advanced in real time.
+pub struct FlywheelModel
+{
+    max_rpm: f32,
+    time_constant: f32,
+    pulses_per_revolution: u16,
+    speed: Cell<f32>,
+    rpm: Cell<f32>,
+    pulses: Cell<f32>,
+    count: Cell<u32>,
+    updated: Cell<Option<Instant>>,
+}
+
+impl FlywheelModel
+{
+    /// Create a new `FlywheelModel` at rest, with one pulse per revolution
+    pub const fn new(
+        max_rpm: f32,
+        time_constant: f32,
+    ) -> Self
+    {
+        Self {
+            max_rpm,
+            time_constant,
+            pulses_per_revolution: 1,
+            speed: Cell::new(0.0),
+            rpm: Cell::new(0.0),
+            pulses: Cell::new(0.0),
+            count: Cell::new(0),
+            updated: Cell::new(None),
+        }
+    }
+
+    /// Set how many tachometer pulses are produced per revolution
+    #[must_use]
+    pub const fn with_pulses_per_revolution(
+        mut self,
+        pulses_per_revolution: u16,
+    ) -> Self
+    {
+        self.pulses_per_revolution = pulses_per_revolution;
+        self
+    }
+
+    /// The flywheel's current speed, in RPM
+    pub fn rpm(&self) -> f32 { self.rpm.get() }
+
+    /// Drive the motor at a speed, in percent
+    pub fn set_speed(
+        &self,
+        percent: f32,
+    )
+    {
+        self.speed.set(percent.clamp(0.0, 100.0));
+    }
+
+    /// Slow the flywheel down by the given RPM, as a dart passing through
+    /// would
+    pub fn disturb(
+        &self,
+        rpm: f32,
+    )
+    {
+        self.rpm.set((self.rpm.get() - rpm).max(0.0));
+    }
+
+    /// Advance the model by the given number of seconds
+    pub fn advance(
+        &self,
+        elapsed: f32,
+    )
+    {
+        if elapsed <= 0.0 {
+            return;
+        }
+
+        // implicit Euler, which stays stable for any step size
+        let target = self.max_rpm * self.speed.get() / 100.0;
+        let rpm = self.rpm.get();
+        let rpm = rpm + (target - rpm) * elapsed / (self.time_constant + elapsed);
+
+        let pulses =
+            self.pulses.get() + rpm / 60.0 * f32::from(self.pulses_per_revolution) * elapsed;
+        let whole = pulses as u32;
+
+        self.rpm.set(rpm);
+        self.pulses.set(pulses - whole as f32);
+        self.count.set(self.count.get().wrapping_add(whole));
+    }
+
+    /// The model as a [Motor]
+    pub fn motor(&self) -> ModelMotor<'_> { ModelMotor { model: self } }
+
+    /// The model's tachometer output as a [PulseCounter]
+    pub fn counter(&self) -> ModelCounter<'_> { ModelCounter { model: self } }
+
+    /// Advance the model in real time, up to now
+    fn sync(&self)
+    {
+        let now = Instant::now();
+
+        if let Some(updated) = self.updated.get() {
+            self.advance(now.saturating_duration_since(updated).as_micros() as f32 / 1_000_000.0);
+        }
+
+        self.updated.set(Some(now));
+    }
+}
+
+/// Model Motor
+///
+/// A [FlywheelModel] driven as a speed-controlled [Motor].
+pub struct ModelMotor<'a>
+{
+    model: &'a FlywheelModel,
+}
+
+impl Motor for ModelMotor<'_>
+{
+    type Error = Infallible;
+
+    fn on(&mut self) -> Result<(), Self::Error>
+    {
+        self.model.sync();
+        self.model.set_speed(100.0);
+        Ok(())
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error>
+    {
+        self.model.sync();
+        self.model.set_speed(0.0);
+        Ok(())
+    }
+
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        self.model.sync();
+        self.model.set_speed(f32::from(percent));
+        Ok(())
+    }
+
+    fn rpm(&self) -> Option<f32>
+    {
+        self.model.sync();
+        Some(self.model.rpm())
+    }
+}
+
+/// Model Counter
+///
+/// The tachometer output of a [FlywheelModel], as a [PulseCounter].
+pub struct ModelCounter<'a>
+{
+    model: &'a FlywheelModel,
+}
+
+impl PulseCounter for ModelCounter<'_>
+{
+    fn count(&mut self) -> u32
+    {
+        self.model.sync();
+        self.model.count.get()
+    }
+}
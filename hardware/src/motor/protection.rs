@@ -0,0 +1,455 @@
+//! ## Stall Protection
+//!
+//! Cuts a motor that stalls or draws too much current, before it burns out.
+
+use core::{fmt, future::Future};
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Ticker};
+
+use super::{CurrentSensor, Direction, LaunchProfile, Motor, MotorCommand, MotorFault};
+
+/// Current Limits
+///
+/// # Fields
+/// - `stall_amps`: The current, in amps, above which the motor is stalling.
+/// - `stall_time`: How long the motor may stay above `stall_amps` before it is
+///   cut. Longer than the motor's inrush at start-up.
+/// - `max_amps`: The current, in amps, at which the motor is cut straight away.
+/// - `period`: How often the current is sampled.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct CurrentLimits
+{
+    pub stall_amps: f32,
+    pub stall_time: Duration,
+    pub max_amps: f32,
+    pub period: Duration,
+}
+
+impl Default for CurrentLimits
+{
+    /// Limits for a typical small brushed motor
+    fn default() -> Self
+    {
+        Self {
+            stall_amps: 3.0,
+            stall_time: Duration::from_millis(250),
+            max_amps: 8.0,
+            period: Duration::from_millis(5),
+        }
+    }
+}
+
+/// Protection Error
+#[derive(Debug)]
+pub enum ProtectionError<E>
+{
+    MotorError(E),
+    Tripped(MotorFault),
+}
+
+/// Current Monitor
+///
+/// Checks samples from a current sensor against the limits.
+///
+/// # Fields
+/// - `sensor`: The current sensor on the motor.
+/// - `limits`: The limits the motor is held to.
+/// - `amps`: The most recent reading, in amps.
+/// - `stalling_since`: When the current went above the stall current, while it
+///   is.
+struct CurrentMonitor<C: CurrentSensor>
+{
+    sensor: C,
+    limits: CurrentLimits,
+    amps: Option<f32>,
+    stalling_since: Option<Instant>,
+}
+
+impl<C: CurrentSensor> CurrentMonitor<C>
+{
+    /// Take a single sample, and check it against the limits
+    ///
+    /// Readings that fail are skipped.
+    fn check(&mut self) -> Option<MotorFault>
+    {
+        let amps = self.sensor.amps()?;
+        self.amps = Some(amps);
+
+        if amps >= self.limits.max_amps {
+            return Some(MotorFault::OverCurrent { amps });
+        }
+
+        if amps < self.limits.stall_amps {
+            self.stalling_since = None;
+            return None;
+        }
+
+        let stalling_since = *self.stalling_since.get_or_insert_with(Instant::now);
+
+        match stalling_since.elapsed() >= self.limits.stall_time {
+            true => Some(MotorFault::Stall { amps }),
+            false => None,
+        }
+    }
+
+    /// Sample the current until the limits are exceeded
+    async fn watch(&mut self) -> MotorFault
+    {
+        let mut ticker = Ticker::every(self.limits.period);
+
+        loop {
+            if let Some(fault) = self.check() {
+                return fault;
+            }
+            ticker.next().await;
+        }
+    }
+
+    /// Run an operation on the motor while watching the current
+    async fn guard<E>(
+        &mut self,
+        operation: impl Future<Output = Result<(), E>>,
+    ) -> Result<(), Either<E, MotorFault>>
+    {
+        if let Some(fault) = self.check() {
+            return Err(Either::Second(fault));
+        }
+
+        match select(operation, self.watch()).await {
+            Either::First(result) => result.map_err(Either::First),
+            Either::Second(fault) => Err(Either::Second(fault)),
+        }
+    }
+}
+
+/// Protected Motor
+///
+/// A motor watched by a current sensor, and cut if it stalls or draws too
+/// much current. The current is sampled throughout every operation, and
+/// while the motor is left running by [Motor::supervise]. A trip turns the
+/// motor off and fails the operation with [ProtectionError::Tripped]; the
+/// fault is also kept until taken with [Motor::take_fault], so it can be
+/// reported. The next command runs the motor as usual.
+///
+/// # Type Parameters
+/// - `M`: The motor being protected.
+/// - `C`: The current sensor on the motor.
+///
+/// # Fields
+/// - `motor`: The motor being protected.
+/// - `monitor`: The current sensor and the limits it is checked against.
+/// - `fault`: The most recent trip, until it is taken.
+pub struct Protected<M: Motor, C: CurrentSensor>
+{
+    motor: M,
+    monitor: CurrentMonitor<C>,
+    fault: Option<MotorFault>,
+}
+
+impl<M: Motor, C: CurrentSensor> Protected<M, C>
+{
+    /// Create a new `Protected` motor from the supplied motor and sensor
+    pub fn new(
+        motor: M,
+        sensor: C,
+        limits: CurrentLimits,
+    ) -> Self
+    {
+        Self {
+            motor,
+            monitor: CurrentMonitor {
+                sensor,
+                limits,
+                amps: None,
+                stalling_since: None,
+            },
+            fault: None,
+        }
+    }
+
+    /// The limits the motor is held to
+    pub fn limits(&self) -> CurrentLimits { self.monitor.limits }
+
+    /// Replace the limits the motor is held to
+    pub fn set_limits(
+        &mut self,
+        limits: CurrentLimits,
+    )
+    {
+        self.monitor.limits = limits;
+    }
+
+    /// Unwrap the motor, discarding its sensor
+    pub fn into_inner(self) -> M { self.motor }
+
+    /// Cut the motor if an operation tripped the limits
+    fn settle(
+        &mut self,
+        result: Result<(), Either<M::Error, MotorFault>>,
+    ) -> Result<(), ProtectionError<M::Error>>
+    {
+        match result {
+            Ok(()) => Ok(()),
+            Err(Either::First(error)) => Err(ProtectionError::MotorError(error)),
+            Err(Either::Second(fault)) => {
+                self.fault = Some(fault);
+                self.monitor.stalling_since = None;
+                self.motor.off().map_err(ProtectionError::MotorError)?;
+                Err(ProtectionError::Tripped(fault))
+            }
+        }
+    }
+}
+
+impl<M: Motor, C: CurrentSensor> Motor for Protected<M, C>
+{
+    type Error = ProtectionError<M::Error>;
+
+    fn on(&mut self) -> Result<(), Self::Error>
+    {
+        self.motor.on().map_err(ProtectionError::MotorError)
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error>
+    {
+        self.motor.off().map_err(ProtectionError::MotorError)
+    }
+
+    fn brake(&mut self) -> Result<(), Self::Error>
+    {
+        self.motor.brake().map_err(ProtectionError::MotorError)
+    }
+
+    async fn start(&mut self) -> Result<(), Self::Error>
+    {
+        let result = self.monitor.guard(self.motor.start()).await;
+        self.settle(result)
+    }
+
+    async fn launch(
+        &mut self,
+        profile: LaunchProfile,
+    ) -> Result<(), Self::Error>
+    {
+        let result = self.monitor.guard(self.motor.launch(profile)).await;
+        self.settle(result)
+    }
+
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        let result = self.monitor.guard(self.motor.set_speed(percent)).await;
+        self.settle(result)
+    }
+
+    async fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        let result = self
+            .monitor
+            .guard(self.motor.drive(direction, percent))
+            .await;
+        self.settle(result)
+    }
+
+    fn rpm(&self) -> Option<f32> { self.motor.rpm() }
+
+    fn current(&self) -> Option<f32> { self.monitor.amps }
+
+    fn thermal_budget(&self) -> Option<f32> { self.motor.thermal_budget() }
+
+    fn take_fault(&mut self) -> Option<MotorFault> { self.fault.take() }
+
+    async fn supervise(&mut self) -> Result<(), Self::Error>
+    {
+        let fault = self.monitor.watch().await;
+        self.settle(Err(Either::Second(fault)))
+    }
+
+    async fn process(
+        &mut self,
+        command: MotorCommand,
+    ) -> Result<(), Self::Error>
+    {
+        // the inner motor handles the command itself, so that its own launch
+        // profile still applies
+        let result = self.monitor.guard(self.motor.process(command)).await;
+        self.settle(result)
+    }
+}
+
+/// Current Trace
+///
+/// A recorded or made-up series of current readings, played back one per
+/// sample as a [CurrentSensor], so that stall protection can be exercised on
+/// the host. Once the trace runs out, the last reading repeats.
+///
+/// - Ex: `CurrentTrace::new(&[0.5, 4.0, 1.2, 1.1, 3.5, 3.6, 3.6, 3.7])`
+///
+/// # Fields
+/// - `samples`: The readings, in amps.
+/// - `index`: The next reading to play back.
+pub struct CurrentTrace<'a>
+{
+    samples: &'a [f32],
+    index: usize,
+}
+
+impl<'a> CurrentTrace<'a>
+{
+    /// Create a new `CurrentTrace` from the supplied readings
+    pub const fn new(samples: &'a [f32]) -> Self { Self { samples, index: 0 } }
+
+    /// Start playing the trace again from the beginning
+    pub fn rewind(&mut self) { self.index = 0; }
+}
+
+impl CurrentSensor for CurrentTrace<'_>
+{
+    fn amps(&mut self) -> Option<f32>
+    {
+        let amps = self
+            .samples
+            .get(self.index)
+            .or(self.samples.last())
+            .copied();
+
+        self.index = (self.index + 1).min(self.samples.len());
+        amps
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use core::convert::Infallible;
+
+    use embassy_futures::block_on;
+    use embassy_time::Timer;
+
+    use super::*;
+
+    /// A motor that only records whether it is running
+    #[derive(Default)]
+    struct Switch
+    {
+        running: bool,
+    }
+
+    impl Motor for Switch
+    {
+        type Error = Infallible;
+
+        fn on(&mut self) -> Result<(), Self::Error>
+        {
+            self.running = true;
+            Ok(())
+        }
+
+        fn off(&mut self) -> Result<(), Self::Error>
+        {
+            self.running = false;
+            Ok(())
+        }
+    }
+
+    const LIMITS: CurrentLimits = CurrentLimits {
+        stall_amps: 3.0,
+        stall_time: Duration::from_millis(50),
+        max_amps: 8.0,
+        period: Duration::from_millis(5),
+    };
+
+    const PULSE: LaunchProfile = LaunchProfile {
+        pulses: 1,
+        on: Duration::from_millis(200),
+        off: Duration::from_millis(0),
+        spin_up: Duration::from_millis(0),
+        ramp: 0,
+    };
+
+    #[test]
+    fn inrush_shorter_than_stall_time_is_ignored()
+    {
+        // 20 ms of inrush, at one reading per 5 ms period
+        let trace = CurrentTrace::new(&[4.5, 4.0, 3.5, 3.2, 1.2, 1.1]);
+        let mut motor = Protected::new(Switch::default(), trace, LIMITS);
+
+        assert!(block_on(motor.launch(PULSE)).is_ok());
+        assert_eq!(motor.take_fault(), None);
+        assert_eq!(motor.current(), Some(1.1));
+    }
+
+    #[test]
+    fn sustained_stall_trips_and_cuts_the_motor()
+    {
+        let trace = CurrentTrace::new(&[0.5, 4.0, 1.2, 3.5, 3.6, 3.6, 3.7]);
+        let mut motor = Protected::new(Switch::default(), trace, LIMITS);
+
+        assert!(matches!(
+            block_on(motor.launch(PULSE)),
+            Err(ProtectionError::Tripped(MotorFault::Stall { amps })) if amps >= 3.0
+        ));
+        assert!(matches!(motor.take_fault(), Some(MotorFault::Stall { .. })));
+        assert_eq!(motor.take_fault(), None);
+        assert!(!motor.into_inner().running);
+    }
+
+    #[test]
+    fn over_current_trips_at_once()
+    {
+        let trace = CurrentTrace::new(&[0.5, 1.0, 9.5, 1.0]);
+        let mut motor = Protected::new(Switch::default(), trace, LIMITS);
+        let start = Instant::now();
+
+        assert!(matches!(
+            block_on(motor.launch(PULSE)),
+            Err(ProtectionError::Tripped(MotorFault::OverCurrent { amps })) if amps == 9.5
+        ));
+        assert!(start.elapsed() < PULSE.on);
+        assert!(!motor.into_inner().running);
+    }
+
+    #[test]
+    fn runs_again_after_a_trip()
+    {
+        let trace = CurrentTrace::new(&[9.5, 1.0]);
+        let mut motor = Protected::new(Switch::default(), trace, LIMITS);
+
+        assert!(block_on(motor.launch(PULSE)).is_err());
+        assert!(block_on(motor.launch(PULSE)).is_ok());
+    }
+
+    #[test]
+    fn failed_readings_are_skipped()
+    {
+        let mut motor = Protected::new(Switch::default(), || None, LIMITS);
+
+        assert!(block_on(motor.launch(PULSE)).is_ok());
+        assert_eq!(motor.current(), None);
+    }
+
+    #[test]
+    fn supervise_trips_a_motor_left_running()
+    {
+        let trace = CurrentTrace::new(&[1.0, 1.0, 3.5]);
+        let mut motor = Protected::new(Switch::default(), trace, LIMITS);
+
+        motor.on().unwrap();
+
+        match block_on(select(motor.supervise(), Timer::after_millis(500))) {
+            Either::First(result) => assert!(matches!(
+                result,
+                Err(ProtectionError::Tripped(MotorFault::Stall { .. }))
+            )),
+            Either::Second(()) => panic!("stall was not caught"),
+        }
+        assert!(!motor.into_inner().running);
+    }
+}
@@ -0,0 +1,184 @@
+//! ## PWM Motor
+//!
+//! Speed control for brushed motors driven through a
+//! [SetDutyCycle](embedded_hal::pwm::SetDutyCycle) channel, with
+//! configurable acceleration and deceleration ramps.
+
+use core::fmt;
+
+use embassy_time::{Duration, Instant, Ticker};
+use embedded_hal::pwm::SetDutyCycle;
+
+use super::Motor;
+
+/// Speed Ramp
+///
+/// How quickly a [PwmMotor] may change speed.
+///
+/// # Fields
+/// - `up`: Maximum acceleration, in percent per second. `0` jumps straight to
+///   the target.
+/// - `down`: Maximum deceleration, in percent per second. `0` jumps straight to
+///   the target.
+/// - `tick`: How often the duty cycle is updated while ramping.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub struct SpeedRamp
+{
+    pub up: u16,
+    pub down: u16,
+    pub tick: Duration,
+}
+
+impl Default for SpeedRamp
+{
+    /// Full speed in half a second, in either direction
+    fn default() -> Self
+    {
+        Self {
+            up: 200,
+            down: 200,
+            tick: Duration::from_millis(10),
+        }
+    }
+}
+
+/// PWM Motor
+///
+/// A motor whose speed is set as a percentage of the channel's
+/// `max_duty_cycle()`.
+///
+/// `MotorCommand::On` and `MotorCommand::Speed` ramp to the target speed,
+/// while `MotorCommand::Off` (and [Motor::off]) cut the duty cycle
+/// immediately, so that stopping is never delayed by a ramp.
+///
+/// # Type Parameters
+/// - `P`: The PWM channel driving the motor.
+///
+/// # Fields
+/// - `pwm`: The PWM channel driving the motor.
+/// - `ramp`: Acceleration and deceleration limits.
+/// - `speed`: The speed, in percent, currently written to the channel.
+/// - `cruise`: The speed `On` runs at; the last non-zero commanded speed.
+pub struct PwmMotor<P: SetDutyCycle>
+{
+    pwm: P,
+    ramp: SpeedRamp,
+    speed: u8,
+    cruise: u8,
+}
+
+impl<P: SetDutyCycle> PwmMotor<P>
+{
+    /// Create a new `PwmMotor` from the supplied channel
+    ///
+    /// The motor starts stopped, with [SpeedRamp::default] and a cruise
+    /// speed of 100%.
+    pub fn new(pwm: P) -> Self
+    {
+        Self {
+            pwm,
+            ramp: SpeedRamp::default(),
+            speed: 0,
+            cruise: 100,
+        }
+    }
+
+    /// Set the acceleration and deceleration limits
+    #[must_use]
+    pub fn with_ramp(
+        mut self,
+        ramp: SpeedRamp,
+    ) -> Self
+    {
+        self.ramp = ramp;
+        self
+    }
+
+    /// The speed, in percent, currently written to the channel
+    pub fn speed(&self) -> u8 { self.speed }
+
+    /// Write the given speed straight to the channel
+    fn write(
+        &mut self,
+        speed: u8,
+    ) -> Result<(), P::Error>
+    {
+        let speed = speed.min(100);
+        let duty = u32::from(self.pwm.max_duty_cycle()) * u32::from(speed) / 100;
+
+        self.pwm.set_duty_cycle(duty as u16)?;
+        self.speed = speed;
+        Ok(())
+    }
+
+    /// Ramp from the current speed to the target
+    ///
+    /// The ramp can be cancelled at any tick by dropping the future, which
+    /// leaves the motor at the last speed written.
+    async fn ramp_to(
+        &mut self,
+        target: u8,
+    ) -> Result<(), P::Error>
+    {
+        let target = target.min(100);
+        let from = self.speed;
+        let rate = if target > from {
+            self.ramp.up
+        }
+        else {
+            self.ramp.down
+        };
+
+        if rate == 0 {
+            return self.write(target);
+        }
+
+        let start = Instant::now();
+        let mut ticker = Ticker::every(self.ramp.tick);
+
+        loop {
+            let travelled = u64::from(rate) * start.elapsed().as_millis() / 1000;
+            let speed = if target > from {
+                (u64::from(from) + travelled).min(u64::from(target))
+            }
+            else {
+                u64::from(from)
+                    .saturating_sub(travelled)
+                    .max(u64::from(target))
+            };
+
+            self.write(speed as u8)?;
+
+            if self.speed == target {
+                return Ok(());
+            }
+
+            ticker.next().await;
+        }
+    }
+}
+
+impl<P: SetDutyCycle> Motor for PwmMotor<P>
+{
+    type Error = P::Error;
+
+    /// Jump straight to the cruise speed, without ramping
+    fn on(&mut self) -> Result<(), Self::Error> { self.write(self.cruise) }
+
+    fn off(&mut self) -> Result<(), Self::Error> { self.write(0) }
+
+    /// Ramp up to the cruise speed
+    async fn start(&mut self) -> Result<(), Self::Error> { self.ramp_to(self.cruise).await }
+
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        if percent > 0 {
+            self.cruise = percent.min(100);
+        }
+
+        self.ramp_to(percent).await
+    }
+}
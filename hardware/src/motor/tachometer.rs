@@ -0,0 +1,224 @@
+//! ## Tachometer
+//!
+//! Measures motor speed from a pulse count, such as a hall-effect or IR
+//! tachometer pulsing once or more per revolution, or a quadrature encoder.
+
+use core::{cell::Cell, fmt};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Instant};
+
+/// Pulse Counter
+///
+/// A running count of tachometer or encoder pulses, typically backed by a
+/// hardware counter such as the ESP32 PCNT, or by an [InterruptCounter] fed
+/// from a GPIO interrupt.
+pub trait PulseCounter
+{
+    /// The number of pulses counted so far
+    ///
+    /// The count wraps on overflow. Counters that can tell direction, such
+    /// as a [QuadratureDecoder], count down when turning backwards.
+    fn count(&mut self) -> u32;
+}
+
+/// Interrupt Counter
+///
+/// A pulse count shared between an interrupt handler, which calls
+/// [InterruptCounter::record] on every pulse, and the task reading it.
+/// Usually kept in a `static`.
+///
+/// - Ex: `static FLYWHEEL_PULSES: InterruptCounter = InterruptCounter::new();`
+pub struct InterruptCounter
+{
+    count: Mutex<CriticalSectionRawMutex, Cell<u32>>,
+}
+
+impl InterruptCounter
+{
+    /// Create a new `InterruptCounter` at zero
+    pub const fn new() -> Self
+    {
+        Self {
+            count: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Record a single pulse
+    pub fn record(&self)
+    {
+        self.count
+            .lock(|count| count.set(count.get().wrapping_add(1)));
+    }
+}
+
+impl Default for InterruptCounter
+{
+    fn default() -> Self { Self::new() }
+}
+
+impl PulseCounter for &InterruptCounter
+{
+    fn count(&mut self) -> u32 { self.count.lock(Cell::get) }
+}
+
+/// Quadrature Decoder
+///
+/// Decodes the A/B channels of a quadrature encoder into a signed count,
+/// counting every edge of both channels (four counts per encoder cycle).
+/// Feed it the levels of both channels whenever either of them changes.
+///
+/// Transitions where both channels change at once cannot be decoded, and are
+/// ignored.
+#[derive(Copy, Clone, fmt::Debug, Default)]
+pub struct QuadratureDecoder
+{
+    state: Option<(bool, bool)>,
+    count: u32,
+}
+
+impl QuadratureDecoder
+{
+    /// Create a new `QuadratureDecoder` at zero
+    pub const fn new() -> Self
+    {
+        Self {
+            state: None,
+            count: 0,
+        }
+    }
+
+    /// Update the decoder with the current level of both channels
+    pub fn update(
+        &mut self,
+        a: bool,
+        b: bool,
+    )
+    {
+        if let Some((last_a, last_b)) = self.state {
+            // A leads B when turning forward
+            match (a != last_a, b != last_b) {
+                (true, false) if a != b => self.count = self.count.wrapping_add(1),
+                (true, false) => self.count = self.count.wrapping_sub(1),
+                (false, true) if a == b => self.count = self.count.wrapping_add(1),
+                (false, true) => self.count = self.count.wrapping_sub(1),
+                _ => {}
+            }
+        }
+
+        self.state = Some((a, b));
+    }
+}
+
+impl PulseCounter for QuadratureDecoder
+{
+    fn count(&mut self) -> u32 { self.count }
+}
+
+/// Tachometer
+///
+/// Converts a [PulseCounter] into revolutions per minute, by comparing the
+/// count between samples.
+///
+/// # Type Parameters
+/// - `C`: The pulse counter.
+///
+/// # Fields
+/// - `counter`: The pulse counter.
+/// - `pulses_per_revolution`: Counts per revolution; four times the cycles per
+///   revolution for a [QuadratureDecoder].
+/// - `smoothing`: Weight, from 0 to 1, given to each new sample; `1.0` disables
+///   filtering.
+/// - `last`: The count and time of the previous sample, once taken.
+/// - `rpm`: The filtered speed, in revolutions per minute.
+pub struct Tachometer<C: PulseCounter>
+{
+    counter: C,
+    pulses_per_revolution: u16,
+    smoothing: f32,
+    last: Option<(u32, Instant)>,
+    rpm: f32,
+}
+
+impl<C: PulseCounter> Tachometer<C>
+{
+    /// Create a new `Tachometer`, with light filtering
+    pub fn new(
+        counter: C,
+        pulses_per_revolution: u16,
+    ) -> Self
+    {
+        Self {
+            counter,
+            pulses_per_revolution: pulses_per_revolution.max(1),
+            smoothing: 0.5,
+            last: None,
+            rpm: 0.0,
+        }
+    }
+
+    /// Set the weight, from 0 to 1, given to each new sample
+    #[must_use]
+    pub fn with_smoothing(
+        mut self,
+        smoothing: f32,
+    ) -> Self
+    {
+        self.smoothing = smoothing.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The speed measured by the most recent sample, in revolutions per
+    /// minute
+    pub fn rpm(&self) -> f32 { self.rpm }
+
+    /// Sample the counter now
+    pub fn sample(&mut self) -> f32 { self.sample_at(Instant::now()) }
+
+    /// Sample the counter now, unless it was sampled within `interval`
+    ///
+    /// Keeps the speed fresh for readers that may ask for it often, without
+    /// sampling so often that each sample only sees a pulse or two.
+    pub fn refresh(
+        &mut self,
+        interval: Duration,
+    ) -> f32
+    {
+        let now = Instant::now();
+
+        match self.last {
+            Some((_, last)) if now.saturating_duration_since(last) < interval => self.rpm,
+            _ => self.sample_at(now),
+        }
+    }
+
+    /// Sample the counter, as of the given time
+    ///
+    /// The first sample only records a starting point, and reports zero.
+    pub fn sample_at(
+        &mut self,
+        now: Instant,
+    ) -> f32
+    {
+        let count = self.counter.count();
+
+        let Some((last_count, last_time)) = self.last
+        else {
+            self.last = Some((count, now));
+            return self.rpm;
+        };
+
+        let elapsed = now.saturating_duration_since(last_time).as_micros() as f32 / 1_000_000.0;
+
+        if elapsed <= 0.0 {
+            return self.rpm;
+        }
+
+        let pulses = count.wrapping_sub(last_count) as i32 as f32;
+        let rpm = pulses / f32::from(self.pulses_per_revolution) / elapsed * 60.0;
+
+        self.rpm += self.smoothing * (rpm - self.rpm);
+        self.last = Some((count, now));
+        self.rpm
+    }
+}
@@ -0,0 +1,593 @@
+//! ## Thermal Limiting
+//!
+//! Keeps motors and solenoids within a thermal budget, so that sustained use
+//! such as full-auto cannot overheat them.
+
+use core::{cell::RefCell, fmt};
+
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Ticker};
+
+use super::{Direction, LaunchProfile, Motor, MotorFault};
+
+/// How often the budget is checked while waiting for it, or while the motor
+/// is left running
+const THERMAL_POLL: Duration = Duration::from_millis(10);
+
+/// Budget above which an actuator counts as cold
+const COLD: f32 = 0.99;
+
+/// Number of slots an [OnTimeWindow] is divided into
+const WINDOW_SLOTS: usize = 8;
+
+/// Thermal Model
+///
+/// Tracks how much heat an actuator has built up, as a budget that is used
+/// up while it runs and recovers while it rests.
+pub trait ThermalModel
+{
+    /// Advance the model
+    ///
+    /// # Parameters
+    ///
+    /// * `elapsed` - Time since the model was last advanced.
+    /// * `load` - How hard the actuator was driven over that time, from 0 when
+    ///   off to 1 when fully on.
+    fn update(
+        &mut self,
+        elapsed: Duration,
+        load: f32,
+    );
+
+    /// The remaining budget, from 1 when cold to 0 when used up
+    fn budget(&self) -> f32;
+
+    /// How long the actuator could now run fully on before the budget is
+    /// used up
+    fn available(&self) -> Duration;
+}
+
+/// On-Time Window
+///
+/// Allows the actuator to be on for up to `allowed` in any rolling `window`.
+/// The window is tracked in eight slots, so on-time drops out of it in steps
+/// of an eighth of the window.
+///
+/// # Fields
+/// - `window`: The length of the rolling window.
+/// - `allowed`: How long the actuator may be on within the window.
+/// - `slots`: On-time, in microseconds, within each slot of the window.
+/// - `current`: The slot being filled.
+/// - `filled`: How much of the current slot has passed.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub struct OnTimeWindow
+{
+    window: Duration,
+    allowed: Duration,
+    slots: [u64; WINDOW_SLOTS],
+    current: usize,
+    filled: Duration,
+}
+
+impl OnTimeWindow
+{
+    /// Create a new `OnTimeWindow`, with no on-time used
+    pub const fn new(
+        window: Duration,
+        allowed: Duration,
+    ) -> Self
+    {
+        Self {
+            window,
+            allowed,
+            slots: [0; WINDOW_SLOTS],
+            current: 0,
+            filled: Duration::from_ticks(0),
+        }
+    }
+
+    /// On-time, in microseconds, within the window
+    fn used(&self) -> u64 { self.slots.iter().sum() }
+}
+
+impl ThermalModel for OnTimeWindow
+{
+    fn update(
+        &mut self,
+        elapsed: Duration,
+        load: f32,
+    )
+    {
+        let slot = (self.window / WINDOW_SLOTS as u32).max(Duration::from_ticks(1));
+        let load = load.clamp(0.0, 1.0);
+        let mut elapsed = elapsed;
+
+        // anything older than the window has dropped out of it anyway
+        if elapsed >= self.window {
+            self.slots = [0; WINDOW_SLOTS];
+            self.filled = Duration::from_ticks(0);
+            elapsed = self.window;
+        }
+
+        while elapsed > Duration::from_ticks(0) {
+            let step = elapsed.min(slot - self.filled);
+
+            self.slots[self.current] += (step.as_micros() as f32 * load) as u64;
+            self.filled += step;
+            elapsed -= step;
+
+            if self.filled >= slot {
+                self.current = (self.current + 1) % WINDOW_SLOTS;
+                self.slots[self.current] = 0;
+                self.filled = Duration::from_ticks(0);
+            }
+        }
+    }
+
+    fn budget(&self) -> f32
+    {
+        match self.allowed.as_micros() {
+            0 => 0.0,
+            allowed => (1.0 - self.used() as f32 / allowed as f32).clamp(0.0, 1.0),
+        }
+    }
+
+    fn available(&self) -> Duration
+    {
+        Duration::from_micros(self.allowed.as_micros().saturating_sub(self.used()))
+    }
+}
+
+/// Heat Model
+///
+/// A simple RC model of the actuator's temperature: heat builds up in
+/// proportion to the load, and leaks away with the cooling time constant.
+/// Heat is measured from 0 when cold to 1 at the limit.
+///
+/// # Fields
+/// - `heat_time`: How long the actuator can run fully on from cold before
+///   reaching the limit, ignoring cooling.
+/// - `cool_time`: Time for the heat to fall by 63% while the actuator is off.
+/// - `heat`: The heat built up, from 0 to 1.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct HeatModel
+{
+    heat_time: Duration,
+    cool_time: Duration,
+    heat: f32,
+}
+
+impl HeatModel
+{
+    /// Create a new, cold `HeatModel`
+    pub const fn new(
+        heat_time: Duration,
+        cool_time: Duration,
+    ) -> Self
+    {
+        Self {
+            heat_time,
+            cool_time,
+            heat: 0.0,
+        }
+    }
+
+    /// The heat built up, from 0 when cold to 1 at the limit
+    pub fn heat(&self) -> f32 { self.heat }
+}
+
+impl ThermalModel for HeatModel
+{
+    fn update(
+        &mut self,
+        elapsed: Duration,
+        load: f32,
+    )
+    {
+        let elapsed = elapsed.as_micros() as f32 / 1_000_000.0;
+        let heat_time = (self.heat_time.as_micros() as f32 / 1_000_000.0).max(f32::EPSILON);
+        let cool_time = (self.cool_time.as_micros() as f32 / 1_000_000.0).max(f32::EPSILON);
+
+        // implicit Euler, which stays stable for any step size
+        self.heat =
+            (self.heat + load.clamp(0.0, 1.0) * elapsed / heat_time) / (1.0 + elapsed / cool_time);
+    }
+
+    fn budget(&self) -> f32 { (1.0 - self.heat).clamp(0.0, 1.0) }
+
+    fn available(&self) -> Duration
+    {
+        Duration::from_micros((self.budget() * self.heat_time.as_micros() as f32) as u64)
+    }
+}
+
+/// Thermal Policy
+///
+/// What a [Thermal] motor does with a command that does not fit in its
+/// budget.
+///
+/// Variants:
+/// - `Refuse`: Fail the command straight away.
+/// - `Throttle`: Hold the command until the actuator has cooled enough, which
+///   slows full-auto down to a rate the actuator can sustain.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub enum ThermalPolicy
+{
+    Refuse,
+    Throttle,
+}
+
+/// Thermal Error
+#[derive(Debug)]
+pub enum ThermalError<E>
+{
+    MotorError(E),
+    Overheated(f32),
+}
+
+/// Heat
+///
+/// A thermal model, kept up to date with the load the motor runs at.
+///
+/// # Fields
+/// - `model`: The thermal model.
+/// - `load`: The load the motor was left running at, from 0 to 1.
+/// - `updated`: When the model was last advanced. Charging a launch in advance
+///   moves this into the future.
+struct Heat<T: ThermalModel>
+{
+    model: T,
+    load: f32,
+    updated: Option<Instant>,
+}
+
+impl<T: ThermalModel> Heat<T>
+{
+    /// Advance the model up to now
+    fn sync(&mut self)
+    {
+        let now = Instant::now();
+
+        match self.updated {
+            Some(updated) if updated >= now => return,
+            Some(updated) => self.model.update(now - updated, self.load),
+            None => {}
+        }
+
+        self.updated = Some(now);
+    }
+
+    /// Whether the given on-time fits in the budget
+    ///
+    /// On-time longer than the whole budget fits once the actuator is cold,
+    /// so that it is not held back forever.
+    fn fits(
+        &mut self,
+        on_time: Duration,
+    ) -> bool
+    {
+        self.sync();
+        self.model.available() >= on_time || self.model.budget() >= COLD
+    }
+
+    /// Set the load the motor is left running at
+    fn set_load(
+        &mut self,
+        load: f32,
+    )
+    {
+        self.sync();
+        self.load = load;
+    }
+
+    /// Use up the given on-time in advance
+    fn charge(
+        &mut self,
+        on_time: Duration,
+    )
+    {
+        self.set_load(0.0);
+        self.model.update(on_time, 1.0);
+        self.updated = self.updated.map(|updated| updated + on_time);
+    }
+
+    /// Wait until the motor has used up its budget while left running
+    ///
+    /// # Returns
+    ///
+    /// * `f32` - The budget left, once it has run out.
+    async fn watch(&mut self) -> f32
+    {
+        let mut ticker = Ticker::every(THERMAL_POLL);
+
+        loop {
+            ticker.next().await;
+            self.sync();
+
+            if self.load > 0.0 && self.model.available() == Duration::from_ticks(0) {
+                return self.model.budget();
+            }
+        }
+    }
+}
+
+/// Thermal Motor
+///
+/// A motor or solenoid held to a thermal budget by a [ThermalModel]. Launches
+/// are charged for their on-time up front, and only start if that fits in
+/// the budget; continuous running is charged as it goes, and the motor is
+/// cut by [Motor::supervise] once the budget runs out. Commands that do not
+/// fit are refused or held back, depending on the [ThermalPolicy], and
+/// refusals and cuts are kept until taken with [Motor::take_fault].
+///
+/// `Launch` commands run the default launch profile, so a motor that needs
+/// its own profile should be given it with [Motor::with_profile] after its
+/// thermal limit.
+///
+/// # Type Parameters
+/// - `M`: The motor being limited.
+/// - `T`: The thermal model.
+///
+/// # Fields
+/// - `motor`: The motor being limited.
+/// - `heat`: The thermal model, and the load the motor runs at; brought up to
+///   date whenever it is read.
+/// - `policy`: What to do with commands that do not fit in the budget.
+/// - `fault`: The most recent refusal or cut, until it is taken.
+pub struct Thermal<M: Motor, T: ThermalModel>
+{
+    motor: M,
+    heat: RefCell<Heat<T>>,
+    policy: ThermalPolicy,
+    fault: Option<MotorFault>,
+}
+
+impl<M: Motor, T: ThermalModel> Thermal<M, T>
+{
+    /// Create a new `Thermal` motor from the supplied motor and model
+    pub fn new(
+        motor: M,
+        model: T,
+        policy: ThermalPolicy,
+    ) -> Self
+    {
+        Self {
+            motor,
+            heat: RefCell::new(Heat {
+                model,
+                load: 0.0,
+                updated: None,
+            }),
+            policy,
+            fault: None,
+        }
+    }
+
+    /// The remaining budget, from 1 when cold to 0 when used up
+    pub fn budget(&self) -> f32
+    {
+        let mut heat = self.heat.borrow_mut();
+
+        heat.sync();
+        heat.model.budget()
+    }
+
+    /// The thermal model, brought up to date
+    pub fn model(&mut self) -> &T
+    {
+        let heat = self.heat.get_mut();
+
+        heat.sync();
+        &heat.model
+    }
+
+    /// Unwrap the motor, discarding its thermal model
+    pub fn into_inner(self) -> M { self.motor }
+
+    /// The thermal model and load, for updating
+    fn heat(&mut self) -> &mut Heat<T> { self.heat.get_mut() }
+
+    /// Wait until the given on-time fits in the budget, or refuse it
+    async fn admit(
+        &mut self,
+        on_time: Duration,
+    ) -> Result<(), ThermalError<M::Error>>
+    {
+        if self.heat().fits(on_time) {
+            return Ok(());
+        }
+
+        if self.policy == ThermalPolicy::Refuse {
+            return Err(self.overheated());
+        }
+
+        let mut ticker = Ticker::every(THERMAL_POLL);
+
+        while !self.heat().fits(on_time) {
+            ticker.next().await;
+        }
+        Ok(())
+    }
+
+    /// Record that the motor has used up its budget
+    fn overheated(&mut self) -> ThermalError<M::Error>
+    {
+        let budget = self.heat().model.budget();
+
+        self.fault = Some(MotorFault::Overheated { budget });
+        ThermalError::Overheated(budget)
+    }
+}
+
+impl<M: Motor, T: ThermalModel> Motor for Thermal<M, T>
+{
+    type Error = ThermalError<M::Error>;
+
+    fn on(&mut self) -> Result<(), Self::Error>
+    {
+        if !self.heat().fits(Duration::from_ticks(1)) {
+            return Err(self.overheated());
+        }
+
+        self.motor.on().map_err(ThermalError::MotorError)?;
+        self.heat().set_load(1.0);
+        Ok(())
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error>
+    {
+        self.heat().set_load(0.0);
+        self.motor.off().map_err(ThermalError::MotorError)
+    }
+
+    fn brake(&mut self) -> Result<(), Self::Error>
+    {
+        self.heat().set_load(0.0);
+        self.motor.brake().map_err(ThermalError::MotorError)
+    }
+
+    /// Wait for the budget, or refuse, then start the motor
+    async fn start(&mut self) -> Result<(), Self::Error>
+    {
+        self.admit(Duration::from_ticks(1)).await?;
+        self.motor.start().await.map_err(ThermalError::MotorError)?;
+        self.heat().set_load(1.0);
+        Ok(())
+    }
+
+    async fn launch(
+        &mut self,
+        profile: LaunchProfile,
+    ) -> Result<(), Self::Error>
+    {
+        let on_time = profile.on_total();
+
+        self.admit(on_time).await?;
+        self.heat().charge(on_time);
+        self.motor
+            .launch(profile)
+            .await
+            .map_err(ThermalError::MotorError)
+    }
+
+    async fn set_speed(
+        &mut self,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        if percent == 0 {
+            return self.off();
+        }
+
+        self.admit(Duration::from_ticks(1)).await?;
+        self.motor
+            .set_speed(percent)
+            .await
+            .map_err(ThermalError::MotorError)?;
+        self.heat().set_load(f32::from(percent.min(100)) / 100.0);
+        Ok(())
+    }
+
+    async fn drive(
+        &mut self,
+        direction: Direction,
+        percent: u8,
+    ) -> Result<(), Self::Error>
+    {
+        if percent > 0 {
+            self.admit(Duration::from_ticks(1)).await?;
+        }
+
+        self.motor
+            .drive(direction, percent)
+            .await
+            .map_err(ThermalError::MotorError)?;
+        self.heat().set_load(f32::from(percent.min(100)) / 100.0);
+        Ok(())
+    }
+
+    fn rpm(&self) -> Option<f32> { self.motor.rpm() }
+
+    fn current(&self) -> Option<f32> { self.motor.current() }
+
+    fn thermal_budget(&self) -> Option<f32> { Some(self.budget()) }
+
+    fn take_fault(&mut self) -> Option<MotorFault>
+    {
+        self.fault.take().or_else(|| self.motor.take_fault())
+    }
+
+    async fn supervise(&mut self) -> Result<(), Self::Error>
+    {
+        match select(self.motor.supervise(), self.heat.get_mut().watch()).await {
+            Either::First(result) => {
+                self.heat().set_load(0.0);
+                result.map_err(ThermalError::MotorError)
+            }
+            Either::Second(_) => {
+                let error = self.overheated();
+
+                self.off()?;
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use embassy_futures::block_on;
+    use embassy_time::Timer;
+
+    use super::*;
+    use crate::motor::FlywheelModel;
+
+    #[test]
+    fn window_drops_old_on_time()
+    {
+        let mut window = OnTimeWindow::new(Duration::from_millis(800), Duration::from_millis(200));
+
+        window.update(Duration::from_millis(100), 1.0);
+        assert_eq!(window.available(), Duration::from_millis(100));
+
+        window.update(Duration::from_millis(800), 0.0);
+        assert_eq!(window.budget(), 1.0);
+    }
+
+    #[test]
+    fn heat_model_cools_while_off()
+    {
+        let mut model = HeatModel::new(Duration::from_millis(100), Duration::from_millis(50));
+
+        model.update(Duration::from_millis(50), 1.0);
+        let hot = model.budget();
+
+        model.update(Duration::from_millis(250), 0.0);
+        assert!(
+            hot < 0.8 && model.budget() > 0.95,
+            "{hot} then {}",
+            model.budget()
+        );
+    }
+
+    #[test]
+    fn budget_is_current_when_read()
+    {
+        let flywheel = FlywheelModel::new(10_000.0, 0.05);
+        let model = HeatModel::new(Duration::from_millis(200), Duration::from_millis(500));
+        let mut motor = Thermal::new(flywheel.motor(), model, ThermalPolicy::Refuse);
+
+        // left running, with nothing but the reads in between
+        motor.on().unwrap();
+        block_on(Timer::after_millis(100));
+        let hot = motor.thermal_budget().unwrap();
+
+        motor.off().unwrap();
+        block_on(Timer::after_millis(300));
+        let cooled = motor.thermal_budget().unwrap();
+
+        assert!(hot < 0.8, "{hot}");
+        assert!(cooled > hot + 0.1, "{hot} then {cooled}");
+    }
+}
@@ -2,11 +2,29 @@
 //!
 //! Facilitates precise positioning of servos through angle conversion and
 //! command processing, supporting pan and tilt functionalities for
-//! [SetDutyCycle](embedded_hal::pwm::SetDutyCycle;)
+//! [SetDutyCycle](embedded_hal::pwm::SetDutyCycle;). Either axis can also be
+//! driven by a [Stepper] through any [Axis].
 use core::fmt;
 
+use embassy_futures::join::join;
+use embassy_time::{Duration, Instant, Ticker};
 use embedded_hal::pwm::SetDutyCycle;
 
+use self::motion::Trajectory;
+pub use self::{
+    axis::{Axis, ServoAxis},
+    calibration::ServoCalibration,
+    limits::{AxisRange, KeepOutZone, LimitPolicy, LimitViolation, SoftLimits, ViolationKind},
+    motion::{AxisKinematics, MotionConfig, MotionProfile},
+    stepper::{Microstepping, Stepper, StepperConfig, TrapezoidalPlan},
+};
+
+mod axis;
+mod calibration;
+mod limits;
+mod motion;
+mod stepper;
+
 /// Servo Command
 ///
 /// Variants:
@@ -29,6 +47,35 @@ pub enum ServoCommand
     PanTilt(u8, u8),
 }
 
+/// Pose
+///
+/// A pan/tilt pair of angles, in degrees, as tracked by a [ServoPair].
+///
+/// The default pose centers both axes at 90 degrees.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Pose
+{
+    pub pan: u8,
+    pub tilt: u8,
+}
+
+impl Pose
+{
+    /// Create a new `Pose` from the supplied angles
+    pub const fn new(
+        pan: u8,
+        tilt: u8,
+    ) -> Self
+    {
+        Self { pan, tilt }
+    }
+}
+
+impl Default for Pose
+{
+    fn default() -> Self { Self::new(90, 90) }
+}
+
 /// Servo Error
 #[derive(Debug)]
 pub enum ServoError<P, T>
@@ -36,6 +83,7 @@ pub enum ServoError<P, T>
     PanError(P),
     TiltError(T),
     BothErrors(P, T),
+    Limit(LimitViolation),
 }
 
 /// Servo Pair
@@ -44,38 +92,229 @@ pub enum ServoError<P, T>
 ///
 /// This struct encapsulates two servos: one for panning and one for tilting.
 /// It is designed to control two axes of motion, typically for camera or sensor
-/// stabilization.
+/// stabilization. Each axis is usually a [ServoAxis], but can be any [Axis],
+/// such as a [Stepper].
 ///
 /// # Type Parameters
-/// - `Pan`: The PWM pin type used to control the pan servo.
-/// - `Tilt`: The PWM pin type used to control the tilt servo.
+/// - `Pan`: The axis used for panning.
+/// - `Tilt`: The axis used for tilting.
 ///
 /// # Fields
-/// - `pan`: The servo motor responsible for panning.
-/// - `tilt`: The servo motor responsible for tilting.
-pub struct ServoPair<Pan: SetDutyCycle, Tilt: SetDutyCycle>
+/// - `pan`: The axis responsible for panning.
+/// - `tilt`: The axis responsible for tilting.
+/// - `motion`: Velocity profile and per-axis limits used when moving.
+/// - `limits`: Angle ranges and keep-out zones every command is checked
+///   against.
+/// - `violation`: The most recent limit violation, until it is taken.
+/// - `position`: The set-points last reached by the axes, if any.
+/// - `active`: The last commanded (non-rest) pose.
+/// - `rest`: The pose the pair moves to on `Rest(true)`.
+/// - `resting`: Whether the pair is currently parked at the rest pose.
+pub struct ServoPair<Pan: Axis, Tilt: Axis>
 {
     pub(crate) pan: Pan,
     pub(crate) tilt: Tilt,
+    motion: MotionConfig,
+    limits: SoftLimits,
+    violation: Option<LimitViolation>,
+    position: Option<(f32, f32)>,
+    active: Pose,
+    rest: Pose,
+    resting: bool,
 }
 
-impl<Pan: SetDutyCycle, Tilt: SetDutyCycle> ServoPair<Pan, Tilt>
+impl<Pan: Axis, Tilt: Axis> ServoPair<Pan, Tilt>
 {
-    /// Create a new `ServoPair` instance from the supplied pins
+    /// Create a new `ServoPair` instance from the supplied axes
+    ///
+    /// Both axes use [MotionConfig::default] and [SoftLimits::default], and
+    /// both the rest pose and the initial active pose default to
+    /// [Pose::default].
+    ///
+    /// The physical position of the axes is unknown until the first move, so
+    /// that move is made without interpolation.
     pub fn new(
         pan: Pan,
         tilt: Tilt,
     ) -> Self
     {
-        Self { pan, tilt }
+        Self {
+            pan,
+            tilt,
+            motion: MotionConfig::default(),
+            limits: SoftLimits::default(),
+            violation: None,
+            position: None,
+            active: Pose::default(),
+            rest: Pose::default(),
+            resting: false,
+        }
+    }
+
+    /// Set the velocity profile and per-axis limits used when moving
+    #[must_use]
+    pub fn with_motion(
+        mut self,
+        motion: MotionConfig,
+    ) -> Self
+    {
+        self.motion = motion;
+        self
+    }
+
+    /// Set the angle ranges and keep-out zones commands are checked against
+    #[must_use]
+    pub fn with_limits(
+        mut self,
+        limits: SoftLimits,
+    ) -> Self
+    {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the pose the pair moves to when it receives `Rest(true)`
+    #[must_use]
+    pub fn with_rest_pose(
+        mut self,
+        rest: Pose,
+    ) -> Self
+    {
+        self.rest = rest;
+        self
+    }
+
+    /// The last commanded active pose
+    ///
+    /// Single-axis commands are applied on top of this pose, and
+    /// `Rest(false)` returns to it.
+    pub fn pose(&self) -> Pose { self.active }
+
+    /// The configured rest pose
+    pub fn rest_pose(&self) -> Pose { self.rest }
+
+    /// Whether the pair is currently parked at the rest pose
+    pub fn is_resting(&self) -> bool { self.resting }
+
+    /// Drive both axes to the given set-points at the same time
+    ///
+    /// `within` is the time until the next set-point is due, which axes such
+    /// as steppers use to pace themselves.
+    async fn write(
+        &mut self,
+        pan: f32,
+        tilt: f32,
+        within: Duration,
+    ) -> Result<(), ServoError<Pan::Error, Tilt::Error>>
+    {
+        match join(self.pan.seek(pan, within), self.tilt.seek(tilt, within)).await {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(pan_error), Ok(())) => Err(ServoError::PanError(pan_error)),
+            (Ok(()), Err(tilt_error)) => Err(ServoError::TiltError(tilt_error)),
+            (Err(pan_error), Err(tilt_error)) => Err(ServoError::BothErrors(pan_error, tilt_error)),
+        }?;
+
+        self.position = Some((pan, tilt));
+        Ok(())
+    }
+
+    /// Move both axes to the target along the configured motion profile
+    ///
+    /// A new set-point is written every tick until both axes arrive. The
+    /// move can be cancelled at any tick by dropping the future, which
+    /// leaves the axes (and [ServoPair::position]) at the last set-point.
+    async fn travel(
+        &mut self,
+        pan: f32,
+        tilt: f32,
+    ) -> Result<(), ServoError<Pan::Error, Tilt::Error>>
+    {
+        let Some((pan_from, tilt_from)) = self.position
+        else {
+            return self.write(pan, tilt, Duration::from_ticks(0)).await;
+        };
+
+        let MotionConfig {
+            profile,
+            pan: pan_kinematics,
+            tilt: tilt_kinematics,
+            tick,
+        } = self.motion;
+
+        // the slower axis sets the pace, and both follow its profile
+        let pan_plan = Trajectory::plan(profile, pan - pan_from, pan_kinematics);
+        let tilt_plan = Trajectory::plan(profile, tilt - tilt_from, tilt_kinematics);
+        let plan = if pan_plan.duration() >= tilt_plan.duration() {
+            pan_plan
+        }
+        else {
+            tilt_plan
+        };
+
+        let start = Instant::now();
+        let mut ticker = Ticker::every(tick);
+
+        loop {
+            let elapsed = start.elapsed().as_micros() as f32 / 1_000_000.0;
+            let progress = plan.progress(elapsed);
+
+            if progress >= 1.0 {
+                return self.write(pan, tilt, tick).await;
+            }
+
+            self.write(
+                pan_from + (pan - pan_from) * progress,
+                tilt_from + (tilt - tilt_from) * progress,
+                tick,
+            )
+            .await?;
+
+            ticker.next().await;
+        }
+    }
+}
+
+impl<Pan: SetDutyCycle, Tilt: SetDutyCycle> ServoPair<ServoAxis<Pan>, ServoAxis<Tilt>>
+{
+    /// Create a new `ServoPair` instance from the supplied PWM channels
+    ///
+    /// Both servos use [ServoCalibration::default].
+    pub fn from_pwm(
+        pan: Pan,
+        tilt: Tilt,
+    ) -> Self
+    {
+        Self::new(ServoAxis::new(pan), ServoAxis::new(tilt))
+    }
+
+    /// Set the calibration used by the pan servo
+    #[must_use]
+    pub fn with_pan_calibration(
+        mut self,
+        calibration: ServoCalibration,
+    ) -> Self
+    {
+        self.pan.calibration = calibration;
+        self
+    }
+
+    /// Set the calibration used by the tilt servo
+    #[must_use]
+    pub fn with_tilt_calibration(
+        mut self,
+        calibration: ServoCalibration,
+    ) -> Self
+    {
+        self.tilt.calibration = calibration;
+        self
     }
 }
 
 /// Servo Trait
 ///
 /// This trait defines the fundamental operations that a servo should support.
-/// It includes methods to move the servo to specified coordinates, convert
-/// an angle to a PWM signal, and process commands received via WebSocket.
+/// It includes methods to move the servo to specified angles and process
+/// commands received via WebSocket.
 ///
 /// The `Servo` trait is designed to be implemented for various types of servos,
 /// allowing for flexibility and extensibility in servo control implementations.
@@ -84,44 +323,29 @@ pub trait Servo
 {
     type Error: fmt::Debug;
 
-    /// Move the servo pair to the specified coordinates
+    /// Move the servo pair to the specified angles
     ///
-    /// This method is responsible for moving the servo to the given x and y
-    /// coordinates. Implementations should ensure that the servo moves
-    /// smoothly and accurately to the specified position.
+    /// This method is responsible for moving the servos to the given pan and
+    /// tilt angles. Implementations should ensure that the servo moves
+    /// smoothly and accurately to the specified position, and that the move
+    /// can be cancelled by dropping the returned future.
     ///
     /// # Parameters
     ///
-    /// * `x` - The x-coordinate to move the servo to.
-    /// * `y` - The y-coordinate to move the servo to.
+    /// * `pan` - The pan angle, in degrees, to move the servo to.
+    /// * `tilt` - The tilt angle, in degrees, to move the servo to.
     ///
     /// # Returns
     ///
     /// * `Result<(), Self::Error>` - Returns `Ok(())` if the servo successfully
-    ///   moves to the specified coordinates, or an error of type `Self::Error`
-    ///   if the operation fails.
+    ///   moves to the specified angles, or an error of type `Self::Error` if
+    ///   the operation fails.
     async fn move_to(
         &mut self,
-        x: u16,
-        y: u16,
+        pan: u8,
+        tilt: u8,
     ) -> Result<(), Self::Error>;
 
-    /// Helper function to convert angle (0 to 180) to PWM signal based on
-    /// 14-bit resolution
-    ///
-    /// This function converts an angle in the range of 0 to 180 degrees to a
-    /// corresponding PWM signal value. The conversion is based on a 14-bit
-    /// resolution.
-    ///
-    /// # Parameters
-    ///
-    /// * `angle` - The angle in degrees to be converted to a PWM signal.
-    ///
-    /// # Returns
-    ///
-    /// * `u16` - The PWM signal value corresponding to the given angle.
-    fn pwm_value(angle: u8) -> u16 { 409 * ((2048 - 409) / 180 * u16::from(angle)) }
-
     /// Process Commands
     ///
     /// This method processes commands sent to the servo. The `ServoCommand`
@@ -141,24 +365,52 @@ pub trait Servo
         &mut self,
         command: ServoCommand,
     ) -> Result<(), Self::Error>;
+
+    /// Take the most recent limit violation
+    ///
+    /// Commands that break the soft limits are either clamped, in which case
+    /// `process` still succeeds, or rejected. Either way the violation is
+    /// kept here so it can be reported back to the client.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<LimitViolation>` - The violation caused by the last command,
+    ///   if any.
+    fn take_violation(&mut self) -> Option<LimitViolation> { None }
+
+    /// Release the servos
+    ///
+    /// Stops the servos holding their position, such as for an emergency
+    /// stop. The servos hold their position again on the next move. Servos
+    /// that cannot be released keep holding their position.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the servos are
+    ///   successfully released, or an error of type `Self::Error` if the
+    ///   operation fails.
+    fn detach(&mut self) -> Result<(), Self::Error> { Ok(()) }
+
+    /// The pan and tilt angles last reached, if known
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(f32, f32)>` - The pan and tilt angles, in degrees, or `None`
+    ///   if the servos have not been moved yet or cannot tell.
+    fn position(&self) -> Option<(f32, f32)> { None }
 }
 
-impl<P: SetDutyCycle, T: SetDutyCycle> Servo for ServoPair<P, T>
+impl<P: Axis, T: Axis> Servo for ServoPair<P, T>
 {
     type Error = ServoError<P::Error, T::Error>;
 
     async fn move_to(
         &mut self,
-        x: u16,
-        y: u16,
+        pan: u8,
+        tilt: u8,
     ) -> Result<(), Self::Error>
     {
-        match (self.pan.set_duty_cycle(x), self.tilt.set_duty_cycle(y)) {
-            (Ok(()), Ok(())) => Ok(()),
-            (Err(pan_error), Ok(())) => Err(ServoError::PanError(pan_error)),
-            (Ok(()), Err(tilt_error)) => Err(ServoError::TiltError(tilt_error)),
-            (Err(pan_error), Err(tilt_error)) => Err(ServoError::BothErrors(pan_error, tilt_error)),
-        }
+        self.travel(f32::from(pan), f32::from(tilt)).await
     }
 
     async fn process(
@@ -166,13 +418,53 @@ impl<P: SetDutyCycle, T: SetDutyCycle> Servo for ServoPair<P, T>
         command: ServoCommand,
     ) -> Result<(), Self::Error>
     {
-        match command {
-            ServoCommand::Rest(_) => todo!(),
-            ServoCommand::Pan(_value) => todo!(),
-            ServoCommand::Tilt(_value) => todo!(),
-            ServoCommand::PanTilt(x, y) => {
-                self.move_to(Self::pwm_value(x), Self::pwm_value(y)).await
+        let (requested, resting) = match command {
+            ServoCommand::Rest(true) => (self.rest, true),
+            ServoCommand::Rest(false) => (self.active, false),
+            ServoCommand::Pan(pan) => (Pose::new(pan, self.active.tilt), false),
+            ServoCommand::Tilt(tilt) => (Pose::new(self.active.pan, tilt), false),
+            ServoCommand::PanTilt(pan, tilt) => (Pose::new(pan, tilt), false),
+        };
+
+        let pose = match self.limits.check(self.position, requested) {
+            None => requested,
+            Some(violation) => {
+                self.violation = Some(violation);
+                violation.resolved.ok_or(ServoError::Limit(violation))?
             }
+        };
+
+        // track the commanded pose up front, so that a command which
+        // supersedes this move still builds on it
+        if !resting {
+            self.active = pose;
+        }
+        self.resting = resting;
+
+        self.move_to(pose.pan, pose.tilt).await
+    }
+
+    fn take_violation(&mut self) -> Option<LimitViolation> { self.violation.take() }
+
+    /// The set-points last reached by the axes, if they have been moved yet
+    ///
+    /// While a move is in progress, or after one has been cancelled, this is
+    /// the intermediate set-point rather than the commanded pose.
+    fn position(&self) -> Option<(f32, f32)> { self.position }
+
+    /// Release both axes
+    ///
+    /// The axes may be moved while released, so their position is treated
+    /// as unknown, and the next move is made without interpolation.
+    fn detach(&mut self) -> Result<(), Self::Error>
+    {
+        self.position = None;
+
+        match (self.pan.detach(), self.tilt.detach()) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(pan_error), Ok(())) => Err(ServoError::PanError(pan_error)),
+            (Ok(()), Err(tilt_error)) => Err(ServoError::TiltError(tilt_error)),
+            (Err(pan_error), Err(tilt_error)) => Err(ServoError::BothErrors(pan_error, tilt_error)),
         }
     }
 }
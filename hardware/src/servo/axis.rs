@@ -0,0 +1,120 @@
+//! ## Axes
+//!
+//! The actuator behind a single pan or tilt axis of a
+//! [ServoPair](super::ServoPair), so that hobby servos and steppers can be
+//! mixed freely.
+
+use core::fmt;
+
+use embassy_time::Duration;
+use embedded_hal::pwm::SetDutyCycle;
+
+use super::ServoCalibration;
+
+/// Axis Trait
+///
+/// A single actuator that can be driven to an angle.
+/// [ServoPair](super::ServoPair) plans each move along its motion profile and
+/// hands the axes one set-point per tick.
+#[allow(async_fn_in_trait)]
+pub trait Axis
+{
+    type Error: fmt::Debug;
+
+    /// Drive the axis to the given set-point
+    ///
+    /// Set-points close to the current angle are spread over `within`, the
+    /// time until the next set-point is due. Axes that take up a new angle
+    /// on their own, such as servos, may return immediately. A `within` of
+    /// zero asks the axis to get there as fast as it safely can.
+    ///
+    /// # Parameters
+    ///
+    /// * `angle` - The set-point, in degrees.
+    /// * `within` - The time until the next set-point is due.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the axis is
+    ///   successfully driven, or an error of type `Self::Error` if the
+    ///   operation fails.
+    async fn seek(
+        &mut self,
+        angle: f32,
+        within: Duration,
+    ) -> Result<(), Self::Error>;
+
+    /// Stop holding the axis, leaving it free to move
+    ///
+    /// The axis holds its position again on the next `seek`. Axes that
+    /// cannot be released keep holding their position.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), Self::Error>` - Returns `Ok(())` if the axis is
+    ///   successfully released, or an error of type `Self::Error` if the
+    ///   operation fails.
+    fn detach(&mut self) -> Result<(), Self::Error> { Ok(()) }
+}
+
+/// Servo Axis
+///
+/// A hobby servo on a PWM channel, with the calibration that maps its angles
+/// onto pulse widths.
+///
+/// # Type Parameters
+/// - `P`: The PWM channel driving the servo.
+///
+/// # Fields
+/// - `pwm`: The PWM channel driving the servo.
+/// - `calibration`: Angle to pulse width mapping for the servo.
+pub struct ServoAxis<P: SetDutyCycle>
+{
+    pwm: P,
+    pub(crate) calibration: ServoCalibration,
+}
+
+impl<P: SetDutyCycle> ServoAxis<P>
+{
+    /// Create a new `ServoAxis` with [ServoCalibration::default]
+    pub fn new(pwm: P) -> Self
+    {
+        Self {
+            pwm,
+            calibration: ServoCalibration::default(),
+        }
+    }
+
+    /// Set the calibration used by the servo
+    #[must_use]
+    pub fn with_calibration(
+        mut self,
+        calibration: ServoCalibration,
+    ) -> Self
+    {
+        self.calibration = calibration;
+        self
+    }
+}
+
+impl<P: SetDutyCycle> Axis for ServoAxis<P>
+{
+    type Error = P::Error;
+
+    /// Write the duty cycle for the angle, computed from the channel's own
+    /// `max_duty_cycle()` so the result is independent of the timer
+    /// resolution
+    async fn seek(
+        &mut self,
+        angle: f32,
+        _within: Duration,
+    ) -> Result<(), Self::Error>
+    {
+        let duty = self.calibration.duty(angle, self.pwm.max_duty_cycle());
+
+        self.pwm.set_duty_cycle(duty)
+    }
+
+    /// Stop the pulses, which leaves most servos limp
+    fn detach(&mut self) -> Result<(), Self::Error> { self.pwm.set_duty_cycle_fully_off() }
+}
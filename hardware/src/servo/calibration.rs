@@ -0,0 +1,88 @@
+//! ## Servo Calibration
+//!
+//! Converts angles into duty cycles using the pulse widths and PWM period of
+//! a specific servo, rather than assuming a fixed timer resolution.
+
+use core::fmt;
+
+/// Servo Calibration
+///
+/// Describes how a single servo axis maps angles onto pulse widths.
+///
+/// The duty cycle is derived from the pulse width relative to the PWM period,
+/// and scaled to the `max_duty_cycle()` reported by the channel, so the same
+/// calibration works regardless of the timer resolution a board configures.
+///
+/// # Fields
+/// - `min_pulse_us`: Pulse width, in microseconds, at 0 degrees.
+/// - `max_pulse_us`: Pulse width, in microseconds, at `angle_range` degrees.
+/// - `period_us`: The PWM period, in microseconds (20 000 for 50 Hz).
+/// - `angle_range`: The mechanical travel of the servo, in degrees.
+/// - `trim`: Offset, in degrees, added to every commanded angle.
+/// - `inverted`: Whether the axis is mounted so that its direction is reversed.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ServoCalibration
+{
+    pub min_pulse_us: u16,
+    pub max_pulse_us: u16,
+    pub period_us: u32,
+    pub angle_range: u8,
+    pub trim: i8,
+    pub inverted: bool,
+}
+
+impl Default for ServoCalibration
+{
+    /// A typical hobby servo: 500-2500 µs over 180 degrees at 50 Hz
+    fn default() -> Self
+    {
+        Self {
+            min_pulse_us: 500,
+            max_pulse_us: 2500,
+            period_us: 20_000,
+            angle_range: 180,
+            trim: 0,
+            inverted: false,
+        }
+    }
+}
+
+impl ServoCalibration
+{
+    /// Pulse width, in microseconds, for the given angle
+    ///
+    /// The trim offset is applied first, the result is clamped to the
+    /// calibrated angle range, and then inverted if required.
+    pub fn pulse_us(
+        &self,
+        angle: f32,
+    ) -> f32
+    {
+        let range = f32::from(self.angle_range);
+        let angle = (angle + f32::from(self.trim)).clamp(0.0, range);
+        let angle = if self.inverted { range - angle } else { angle };
+
+        let span = f32::from(self.max_pulse_us) - f32::from(self.min_pulse_us);
+
+        f32::from(self.min_pulse_us) + span * angle / range
+    }
+
+    /// Duty cycle for the given angle, scaled to `max_duty`
+    ///
+    /// # Parameters
+    ///
+    /// * `angle` - The angle, in degrees.
+    /// * `max_duty` - The value reported by
+    ///   [SetDutyCycle::max_duty_cycle](embedded_hal::pwm::SetDutyCycle::max_duty_cycle)
+    ///   for the channel driving this servo.
+    pub fn duty(
+        &self,
+        angle: f32,
+        max_duty: u16,
+    ) -> u16
+    {
+        let duty = self.pulse_us(angle) * f32::from(max_duty) / self.period_us as f32;
+
+        (duty + 0.5).clamp(0.0, f32::from(max_duty)) as u16
+    }
+}
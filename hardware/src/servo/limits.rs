@@ -0,0 +1,341 @@
+//! ## Servo Soft Limits
+//!
+//! Keeps the turret away from regions it must never aim at. Each axis has its
+//! own angle range, and any number of polygonal keep-out zones can be placed
+//! in pan/tilt space. Because both axes of a [ServoPair](super::ServoPair)
+//! arrive at the same time, every move is a straight line in pan/tilt space,
+//! so the whole path is checked, not just the target.
+
+use core::fmt;
+
+use super::Pose;
+
+/// Axis Range
+///
+/// The inclusive range of angles, in degrees, a single axis may be commanded
+/// to.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AxisRange
+{
+    pub min: u8,
+    pub max: u8,
+}
+
+impl AxisRange
+{
+    /// Create a new `AxisRange` from the supplied bounds
+    pub const fn new(
+        min: u8,
+        max: u8,
+    ) -> Self
+    {
+        Self { min, max }
+    }
+
+    /// Whether the angle lies within the range
+    pub fn contains(
+        &self,
+        angle: u8,
+    ) -> bool
+    {
+        (self.min..=self.max).contains(&angle)
+    }
+
+    /// The closest angle within the range
+    pub fn clamp(
+        &self,
+        angle: u8,
+    ) -> u8
+    {
+        angle.clamp(self.min, self.max)
+    }
+}
+
+impl Default for AxisRange
+{
+    fn default() -> Self { Self::new(0, 180) }
+}
+
+/// Keep-Out Zone
+///
+/// A polygon in pan/tilt space that the turret must neither aim at nor sweep
+/// through. Vertices are given in order, with either winding; the polygon is
+/// closed implicitly.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub struct KeepOutZone
+{
+    pub vertices: &'static [Pose],
+}
+
+impl KeepOutZone
+{
+    /// Create a new `KeepOutZone` from the supplied vertices
+    pub const fn new(vertices: &'static [Pose]) -> Self { Self { vertices } }
+
+    /// Whether the point lies inside the polygon
+    fn contains(
+        &self,
+        (pan, tilt): (f32, f32),
+    ) -> bool
+    {
+        let mut inside = false;
+
+        for (a, b) in self.edges() {
+            if (a.1 > tilt) != (b.1 > tilt) && pan < (b.0 - a.0) * (tilt - a.1) / (b.1 - a.1) + a.0
+            {
+                inside = !inside;
+            }
+        }
+
+        inside
+    }
+
+    /// How far along the segment, from 0 to 1, it first enters the polygon
+    ///
+    /// A segment that starts inside the polygon is only allowed to leave it,
+    /// so that a turret which finds itself in a zone can always back out.
+    fn entry(
+        &self,
+        from: (f32, f32),
+        to: (f32, f32),
+    ) -> Option<f32>
+    {
+        if self.contains(from) {
+            return self.contains(to).then_some(0.0);
+        }
+
+        let crossing = self
+            .edges()
+            .filter_map(|edge| intersection((from, to), edge))
+            .reduce(f32::min);
+
+        crossing.or_else(|| self.contains(to).then_some(1.0))
+    }
+
+    /// The polygon's edges, including the closing edge
+    fn edges(&self) -> impl Iterator<Item = ((f32, f32), (f32, f32))> + '_
+    {
+        let points = self.vertices.iter().map(|vertex| point(*vertex));
+
+        points.clone().zip(points.cycle().skip(1))
+    }
+}
+
+/// Limit Policy
+///
+/// Variants:
+/// - `Clamp`: Move as close to the requested pose as the limits allow.
+/// - `Reject`: Refuse the command and stay put.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LimitPolicy
+{
+    Clamp,
+    Reject,
+}
+
+/// Violation Kind
+///
+/// Variants:
+/// - `Range`: The requested pose lies outside an axis range.
+///   - Ex: `"Range"`
+/// - `KeepOut`: The move would enter the keep-out zone with the given index.
+///   - Ex: `{ "KeepOut": { "zone": 0 } }`
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ViolationKind
+{
+    Range,
+    KeepOut
+    {
+        zone: u8,
+    },
+}
+
+/// Limit Violation
+///
+/// Describes a command that broke the soft limits, and what was done about
+/// it.
+///
+/// # Fields
+/// - `kind`: Which limit was violated.
+/// - `requested`: The pose the command asked for.
+/// - `resolved`: The pose moved to instead, or `None` if the command was
+///   rejected.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LimitViolation
+{
+    pub kind: ViolationKind,
+    pub requested: Pose,
+    pub resolved: Option<Pose>,
+}
+
+/// Soft Limits
+///
+/// # Fields
+/// - `pan`: Allowed range for the pan axis.
+/// - `tilt`: Allowed range for the tilt axis.
+/// - `keep_out`: Regions of pan/tilt space the turret must stay out of.
+/// - `policy`: Whether violating commands are clamped or rejected.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq)]
+pub struct SoftLimits
+{
+    pub pan: AxisRange,
+    pub tilt: AxisRange,
+    pub keep_out: &'static [KeepOutZone],
+    pub policy: LimitPolicy,
+}
+
+impl Default for SoftLimits
+{
+    /// The full range of a standard servo, with no keep-out zones
+    fn default() -> Self
+    {
+        Self {
+            pan: AxisRange::default(),
+            tilt: AxisRange::default(),
+            keep_out: &[],
+            policy: LimitPolicy::Clamp,
+        }
+    }
+}
+
+impl SoftLimits
+{
+    /// Check a move against the limits
+    ///
+    /// # Parameters
+    ///
+    /// * `from` - Where the servos currently are, if known. When unknown, only
+    ///   the target itself can be checked.
+    /// * `to` - The requested pose.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<LimitViolation>` - `None` if the move is allowed as requested,
+    ///   otherwise the violation and, under [LimitPolicy::Clamp], the pose to
+    ///   move to instead.
+    pub fn check(
+        &self,
+        from: Option<(f32, f32)>,
+        to: Pose,
+    ) -> Option<LimitViolation>
+    {
+        let violation = |kind, resolved| {
+            Some(LimitViolation {
+                kind,
+                requested: to,
+                resolved,
+            })
+        };
+
+        let mut target = to;
+        let mut kind = None;
+
+        if !self.pan.contains(to.pan) || !self.tilt.contains(to.tilt) {
+            if self.policy == LimitPolicy::Reject {
+                return violation(ViolationKind::Range, None);
+            }
+
+            target = Pose::new(self.pan.clamp(to.pan), self.tilt.clamp(to.tilt));
+            kind = Some(ViolationKind::Range);
+        }
+
+        let from = from.unwrap_or(point(target));
+
+        if let Some((zone, entry)) = self.entry(from, target) {
+            let kind = ViolationKind::KeepOut { zone };
+
+            return match self.policy {
+                LimitPolicy::Clamp => violation(kind, self.back_off(from, target, entry)),
+                LimitPolicy::Reject => violation(kind, None),
+            };
+        }
+
+        kind.and_then(|kind| violation(kind, Some(target)))
+    }
+
+    /// The first keep-out zone the move enters, and how far along it does
+    fn entry(
+        &self,
+        from: (f32, f32),
+        to: Pose,
+    ) -> Option<(u8, f32)>
+    {
+        self.keep_out
+            .iter()
+            .enumerate()
+            .filter_map(|(index, zone)| Some((index as u8, zone.entry(from, point(to))?)))
+            .reduce(|first, other| {
+                if other.1 < first.1 {
+                    other
+                }
+                else {
+                    first
+                }
+            })
+    }
+
+    /// The furthest whole-degree pose short of `entry` that can be reached
+    /// without entering any zone, down to staying at `from`
+    fn back_off(
+        &self,
+        from: (f32, f32),
+        to: Pose,
+        entry: f32,
+    ) -> Option<Pose>
+    {
+        let (pan, tilt) = point(to);
+        let (d_pan, d_tilt) = (pan - from.0, tilt - from.1);
+
+        // step back one degree of travel at a time
+        let length = d_pan.abs().max(d_tilt.abs());
+        let step = if length > 0.0 { 1.0 / length } else { 1.0 };
+        let mut along = (entry - step).max(0.0);
+
+        loop {
+            let candidate = Pose::new(
+                round(from.0 + d_pan * along),
+                round(from.1 + d_tilt * along),
+            );
+
+            if self.pan.contains(candidate.pan)
+                && self.tilt.contains(candidate.tilt)
+                && self.entry(from, candidate).is_none()
+            {
+                return Some(candidate);
+            }
+
+            // the last candidate is the current pose itself
+            if along <= 0.0 {
+                return None;
+            }
+            along = (along - step).max(0.0);
+        }
+    }
+}
+
+/// Where the segment `a` first crosses the segment `b`, as a fraction of `a`
+fn intersection(
+    (from, to): ((f32, f32), (f32, f32)),
+    (start, end): ((f32, f32), (f32, f32)),
+) -> Option<f32>
+{
+    let cross = |a: (f32, f32), b: (f32, f32)| a.0 * b.1 - a.1 * b.0;
+
+    let r = (to.0 - from.0, to.1 - from.1);
+    let s = (end.0 - start.0, end.1 - start.1);
+    let q = (start.0 - from.0, start.1 - from.1);
+
+    let denominator = cross(r, s);
+    if denominator == 0.0 {
+        return None;
+    }
+
+    let along = cross(q, s) / denominator;
+    let across = cross(q, r) / denominator;
+
+    ((0.0..=1.0).contains(&along) && (0.0..=1.0).contains(&across)).then_some(along)
+}
+
+fn point(pose: Pose) -> (f32, f32) { (f32::from(pose.pan), f32::from(pose.tilt)) }
+
+fn round(angle: f32) -> u8 { (angle + 0.5).clamp(0.0, f32::from(u8::MAX)) as u8 }
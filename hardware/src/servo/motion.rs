@@ -0,0 +1,204 @@
+//! ## Servo Motion Planning
+//!
+//! Plans smooth, time-synchronised moves for a pan/tilt pair. Each axis is
+//! limited by its own maximum speed and acceleration; the slowest axis sets
+//! the duration of the move and the other axis follows the same normalised
+//! velocity profile, so both arrive at the same time.
+
+use core::fmt;
+
+use embassy_time::Duration;
+
+/// Motion Profile
+///
+/// Variants:
+/// - `Instant`: Jump straight to the target, with no interpolation.
+/// - `Trapezoidal`: Constant acceleration up to the maximum speed, cruise, then
+///   constant deceleration.
+/// - `SCurve`: Like `Trapezoidal`, but the acceleration itself ramps in and
+///   out, removing the jerk at the start and end of each ramp.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MotionProfile
+{
+    Instant,
+    Trapezoidal,
+    SCurve,
+}
+
+/// Axis Kinematics
+///
+/// Speed and acceleration limits for a single axis.
+///
+/// # Fields
+/// - `max_speed`: Maximum angular speed, in degrees per second.
+/// - `max_acceleration`: Maximum angular acceleration, in degrees per second
+///   squared.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AxisKinematics
+{
+    pub max_speed: f32,
+    pub max_acceleration: f32,
+}
+
+impl Default for AxisKinematics
+{
+    fn default() -> Self
+    {
+        Self {
+            max_speed: 180.0,
+            max_acceleration: 720.0,
+        }
+    }
+}
+
+/// Motion Config
+///
+/// # Fields
+/// - `profile`: The velocity profile used for every move.
+/// - `pan`: Speed and acceleration limits for the pan axis.
+/// - `tilt`: Speed and acceleration limits for the tilt axis.
+/// - `tick`: How often a new set-point is written to the servos while moving.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct MotionConfig
+{
+    pub profile: MotionProfile,
+    pub pan: AxisKinematics,
+    pub tilt: AxisKinematics,
+    pub tick: Duration,
+}
+
+impl Default for MotionConfig
+{
+    /// An S-curve profile, updated once per 50 Hz PWM period
+    fn default() -> Self
+    {
+        Self {
+            profile: MotionProfile::SCurve,
+            pan: AxisKinematics::default(),
+            tilt: AxisKinematics::default(),
+            tick: Duration::from_millis(20),
+        }
+    }
+}
+
+/// A single-axis move, planned against that axis' kinematic limits
+#[derive(Copy, Clone, fmt::Debug)]
+pub(crate) struct Trajectory
+{
+    profile: MotionProfile,
+    distance: f32,
+    speed: f32,
+    ramp: f32,
+    duration: f32,
+}
+
+impl Trajectory
+{
+    /// Plan the fastest move over `distance` degrees allowed by `kinematics`
+    pub(crate) fn plan(
+        profile: MotionProfile,
+        distance: f32,
+        kinematics: AxisKinematics,
+    ) -> Self
+    {
+        let distance = distance.abs();
+        let AxisKinematics {
+            max_speed,
+            max_acceleration,
+        } = kinematics;
+
+        // an S-curve ramp averages two thirds of its peak acceleration, so it
+        // needs a longer ramp to stay within the same limit
+        let stretch = match profile {
+            MotionProfile::Instant => 0.0,
+            MotionProfile::Trapezoidal => 1.0,
+            MotionProfile::SCurve => 1.5,
+        };
+
+        if stretch == 0.0 || distance == 0.0 || max_speed <= 0.0 || max_acceleration <= 0.0 {
+            return Self {
+                profile: MotionProfile::Instant,
+                distance,
+                speed: 0.0,
+                ramp: 0.0,
+                duration: 0.0,
+            };
+        }
+
+        // both ramps together cover `speed * ramp` degrees; if that is
+        // further than the whole move, the axis never reaches full speed
+        let ramp = stretch * max_speed / max_acceleration;
+        let (speed, ramp) = if max_speed * ramp <= distance {
+            (max_speed, ramp)
+        }
+        else {
+            let speed = sqrt(distance * max_acceleration / stretch);
+            (speed, stretch * speed / max_acceleration)
+        };
+
+        Self {
+            profile,
+            distance,
+            speed,
+            ramp,
+            duration: distance / speed + ramp,
+        }
+    }
+
+    /// Total duration of the move, in seconds
+    pub(crate) fn duration(&self) -> f32 { self.duration }
+
+    /// Fraction of the move, from 0 to 1, completed `elapsed` seconds in
+    pub(crate) fn progress(
+        &self,
+        elapsed: f32,
+    ) -> f32
+    {
+        if elapsed >= self.duration || self.profile == MotionProfile::Instant {
+            return 1.0;
+        }
+
+        let covered = if elapsed < self.ramp {
+            self.ramp_distance(elapsed)
+        }
+        else if elapsed > self.duration - self.ramp {
+            self.distance - self.ramp_distance(self.duration - elapsed)
+        }
+        else {
+            self.ramp_distance(self.ramp) + self.speed * (elapsed - self.ramp)
+        };
+
+        (covered / self.distance).clamp(0.0, 1.0)
+    }
+
+    /// Distance covered `elapsed` seconds into the acceleration ramp
+    fn ramp_distance(
+        &self,
+        elapsed: f32,
+    ) -> f32
+    {
+        let x = elapsed / self.ramp;
+
+        match self.profile {
+            MotionProfile::Instant => self.distance,
+            MotionProfile::Trapezoidal => self.speed * self.ramp * x * x / 2.0,
+            // integral of a smoothstep velocity ramp
+            MotionProfile::SCurve => self.speed * self.ramp * (x * x * x - x * x * x * x / 2.0),
+        }
+    }
+}
+
+/// Square root for `no_std`, via a bit-level estimate refined by Newton's
+/// method
+pub(crate) fn sqrt(value: f32) -> f32
+{
+    if value <= 0.0 {
+        return 0.0;
+    }
+
+    let mut root = f32::from_bits((value.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..4 {
+        root = 0.5 * (root + value / root);
+    }
+    root
+}
@@ -0,0 +1,501 @@
+//! ## Stepper Axis
+//!
+//! Positions a stepper motor through a step/dir driver such as the A4988 or
+//! TMC2208, with trapezoidal acceleration and step-accurate position
+//! tracking. A [Stepper] can be used as either axis of a
+//! [ServoPair](super::ServoPair).
+
+use core::fmt;
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::OutputPin;
+
+use super::{motion::sqrt, Axis, AxisKinematics};
+use crate::motor::Direction;
+
+/// Microstepping
+///
+/// The microstep resolution the driver is configured for, usually by
+/// strapping its `MS` pins. The A4988 supports up to `Sixteenth`, while the
+/// TMC2208 supports up to `TwoHundredFiftySixth` over UART.
+///
+/// Variants:
+/// - `Full`: 1 microstep per full step.
+/// - `Half`: 2 microsteps per full step.
+/// - `Quarter`: 4 microsteps per full step.
+/// - `Eighth`: 8 microsteps per full step.
+/// - `Sixteenth`: 16 microsteps per full step.
+/// - `ThirtySecond`: 32 microsteps per full step.
+/// - `SixtyFourth`: 64 microsteps per full step.
+/// - `OneHundredTwentyEighth`: 128 microsteps per full step.
+/// - `TwoHundredFiftySixth`: 256 microsteps per full step.
+#[derive(Copy, Clone, fmt::Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Microstepping
+{
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+    SixtyFourth,
+    OneHundredTwentyEighth,
+    TwoHundredFiftySixth,
+}
+
+impl Microstepping
+{
+    /// Number of microsteps per full step
+    pub const fn factor(self) -> u16
+    {
+        match self {
+            Microstepping::Full => 1,
+            Microstepping::Half => 2,
+            Microstepping::Quarter => 4,
+            Microstepping::Eighth => 8,
+            Microstepping::Sixteenth => 16,
+            Microstepping::ThirtySecond => 32,
+            Microstepping::SixtyFourth => 64,
+            Microstepping::OneHundredTwentyEighth => 128,
+            Microstepping::TwoHundredFiftySixth => 256,
+        }
+    }
+}
+
+/// Stepper Config
+///
+/// # Fields
+/// - `steps_per_revolution`: Full steps per revolution of the axis, including
+///   any gearing between the motor and the axis.
+/// - `microstepping`: The microstep resolution the driver is configured for.
+/// - `kinematics`: Speed and acceleration limits for standalone moves.
+/// - `pulse`: How long the step pin is held high for each step, which also
+///   serves as the direction setup time.
+/// - `inverted`: Whether the axis is mounted so that its direction is reversed.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct StepperConfig
+{
+    pub steps_per_revolution: u32,
+    pub microstepping: Microstepping,
+    pub kinematics: AxisKinematics,
+    pub pulse: Duration,
+    pub inverted: bool,
+}
+
+impl Default for StepperConfig
+{
+    /// A 1.8 degree motor, direct drive, at 1/16 microstepping
+    fn default() -> Self
+    {
+        Self {
+            steps_per_revolution: 200,
+            microstepping: Microstepping::Sixteenth,
+            kinematics: AxisKinematics::default(),
+            pulse: Duration::from_micros(2),
+            inverted: false,
+        }
+    }
+}
+
+impl StepperConfig
+{
+    /// Microsteps per degree of axis travel
+    pub fn steps_per_degree(&self) -> f32
+    {
+        (self.steps_per_revolution * u32::from(self.microstepping.factor())) as f32 / 360.0
+    }
+}
+
+/// Trapezoidal Plan
+///
+/// Step timing for a move of a whole number of steps: constant acceleration
+/// from standstill up to the maximum speed, cruise, then constant
+/// deceleration back to standstill. Moves too short to reach the maximum
+/// speed accelerate for the first half and decelerate for the second.
+///
+/// # Fields
+/// - `steps`: Number of steps in the move.
+/// - `speed`: Peak speed, in steps per second.
+/// - `acceleration`: Acceleration, in steps per second squared.
+/// - `ramp`: Number of steps, possibly fractional, spent accelerating.
+#[derive(Copy, Clone, fmt::Debug, PartialEq)]
+pub struct TrapezoidalPlan
+{
+    steps: u32,
+    speed: f32,
+    acceleration: f32,
+    ramp: f32,
+}
+
+impl TrapezoidalPlan
+{
+    /// Plan a move of `steps` steps
+    ///
+    /// A `max_speed` of zero or less makes every step due immediately, and
+    /// an `acceleration` of zero or less runs the whole move at `max_speed`.
+    pub fn new(
+        steps: u32,
+        max_speed: f32,
+        acceleration: f32,
+    ) -> Self
+    {
+        if max_speed <= 0.0 || acceleration <= 0.0 {
+            return Self {
+                steps,
+                speed: max_speed,
+                acceleration: 0.0,
+                ramp: 0.0,
+            };
+        }
+
+        let ramp = (max_speed * max_speed / (2.0 * acceleration)).min(steps as f32 / 2.0);
+
+        Self {
+            steps,
+            speed: sqrt(2.0 * acceleration * ramp),
+            acceleration,
+            ramp,
+        }
+    }
+
+    /// Number of steps in the move
+    pub fn steps(&self) -> u32 { self.steps }
+
+    /// When the given step (counting from 1) is due, relative to the start of
+    /// the move
+    pub fn due(
+        &self,
+        step: u32,
+    ) -> Duration
+    {
+        let seconds = self.time(step.min(self.steps) as f32);
+
+        Duration::from_micros((seconds * 1_000_000.0) as u64)
+    }
+
+    /// Total duration of the move
+    pub fn duration(&self) -> Duration { self.due(self.steps) }
+
+    /// Time, in seconds, at which the move has covered `distance` steps
+    fn time(
+        &self,
+        distance: f32,
+    ) -> f32
+    {
+        if self.speed <= 0.0 {
+            return 0.0;
+        }
+
+        if self.acceleration <= 0.0 {
+            return distance / self.speed;
+        }
+
+        let steps = self.steps as f32;
+        let ramp_time = self.speed / self.acceleration;
+        let cruise_time = (steps - 2.0 * self.ramp) / self.speed;
+
+        if distance <= self.ramp {
+            sqrt(2.0 * distance / self.acceleration)
+        }
+        else if distance <= steps - self.ramp {
+            ramp_time + (distance - self.ramp) / self.speed
+        }
+        else {
+            2.0 * ramp_time + cruise_time - sqrt(2.0 * (steps - distance) / self.acceleration)
+        }
+    }
+}
+
+/// Stepper
+///
+/// A stepper motor behind a step/dir driver. Every step is counted, so the
+/// axis always knows its position relative to where it started, or to where
+/// it was last told it is with [Stepper::set_angle].
+///
+/// The driver is enabled automatically before each move, and stays enabled,
+/// holding its position, until [Stepper::disable] is called. The enable pin
+/// is treated as active-low, as on the A4988 and TMC2208.
+///
+/// # Type Parameters
+/// - `Step`: The output pin connected to the driver's `STEP` input.
+/// - `Dir`: The output pin connected to the driver's `DIR` input.
+/// - `Enable`: The output pin connected to the driver's `EN` input.
+///
+/// # Fields
+/// - `step`: The output pin connected to the driver's `STEP` input.
+/// - `dir`: The output pin connected to the driver's `DIR` input.
+/// - `enable`: The output pin connected to the driver's `EN` input.
+/// - `config`: Step resolution, kinematic limits and pin timing.
+/// - `position`: The current position, in microsteps from the origin.
+/// - `origin`: The angle, in degrees, of position `0`.
+/// - `direction`: The direction the `DIR` pin is set to, once it has been set.
+/// - `enabled`: Whether the driver is currently enabled.
+pub struct Stepper<Step, Dir, Enable>
+where
+    Step: OutputPin,
+    Dir: OutputPin<Error = Step::Error>,
+    Enable: OutputPin<Error = Step::Error>,
+{
+    step: Step,
+    dir: Dir,
+    enable: Enable,
+    config: StepperConfig,
+    position: i32,
+    origin: f32,
+    direction: Option<Direction>,
+    enabled: bool,
+}
+
+impl<Step, Dir, Enable> Stepper<Step, Dir, Enable>
+where
+    Step: OutputPin,
+    Dir: OutputPin<Error = Step::Error>,
+    Enable: OutputPin<Error = Step::Error>,
+{
+    /// Create a new `Stepper` from the supplied pins
+    ///
+    /// The stepper uses [StepperConfig::default], and is assumed to start
+    /// centred at 90 degrees, matching [Pose::default](super::Pose). Pins are
+    /// not touched until the first move.
+    pub fn new(
+        step: Step,
+        dir: Dir,
+        enable: Enable,
+    ) -> Self
+    {
+        Self {
+            step,
+            dir,
+            enable,
+            config: StepperConfig::default(),
+            position: 0,
+            origin: 90.0,
+            direction: None,
+            enabled: false,
+        }
+    }
+
+    /// Set the step resolution, kinematic limits and pin timing
+    #[must_use]
+    pub fn with_config(
+        mut self,
+        config: StepperConfig,
+    ) -> Self
+    {
+        self.config = config;
+        self
+    }
+
+    /// Set the angle the stepper starts at
+    #[must_use]
+    pub fn with_origin(
+        mut self,
+        angle: f32,
+    ) -> Self
+    {
+        self.set_angle(angle);
+        self
+    }
+
+    /// The current position, in microsteps from the origin
+    pub fn position(&self) -> i32 { self.position }
+
+    /// The current angle, in degrees
+    pub fn angle(&self) -> f32
+    {
+        self.origin + self.position as f32 / self.config.steps_per_degree()
+    }
+
+    /// Declare the current position to be the given angle, such as after
+    /// homing against a switch
+    pub fn set_angle(
+        &mut self,
+        angle: f32,
+    )
+    {
+        self.origin = angle;
+        self.position = 0;
+    }
+
+    /// Enable the driver, energising the motor
+    pub fn enable(&mut self) -> Result<(), Step::Error>
+    {
+        self.enable.set_low()?;
+        self.enabled = true;
+        Ok(())
+    }
+
+    /// Disable the driver, letting the motor turn freely
+    ///
+    /// Any external force may then move the axis without the steps being
+    /// counted, so the position should be re-established with
+    /// [Stepper::set_angle] before relying on it.
+    pub fn disable(&mut self) -> Result<(), Step::Error>
+    {
+        self.enable.set_high()?;
+        self.enabled = false;
+        Ok(())
+    }
+
+    /// Move to an absolute position, in microsteps from the origin
+    ///
+    /// The move follows a [TrapezoidalPlan] within the configured kinematic
+    /// limits. It can be cancelled at any step by dropping the future, which
+    /// leaves [Stepper::position] at the last step taken.
+    pub async fn move_to(
+        &mut self,
+        target: i32,
+    ) -> Result<(), Step::Error>
+    {
+        let steps = target.abs_diff(self.position);
+
+        if steps == 0 {
+            return Ok(());
+        }
+
+        let direction = self.prepare(target)?;
+        let steps_per_degree = self.config.steps_per_degree();
+        let plan = TrapezoidalPlan::new(
+            steps,
+            self.config.kinematics.max_speed * steps_per_degree,
+            self.config.kinematics.max_acceleration * steps_per_degree,
+        );
+
+        let start = Instant::now() + self.config.pulse;
+
+        for step in 1..=plan.steps() {
+            Timer::at(start + plan.due(step)).await;
+            self.pulse(direction).await?;
+        }
+        Ok(())
+    }
+
+    /// Move by a number of microsteps relative to the current position
+    pub async fn move_by(
+        &mut self,
+        steps: i32,
+    ) -> Result<(), Step::Error>
+    {
+        self.move_to(self.position.saturating_add(steps)).await
+    }
+
+    /// Move to an absolute angle, in degrees, to the nearest microstep
+    pub async fn move_to_angle(
+        &mut self,
+        angle: f32,
+    ) -> Result<(), Step::Error>
+    {
+        self.move_to(self.steps_for(angle)).await
+    }
+
+    /// The position, in microsteps from the origin, nearest to an angle
+    fn steps_for(
+        &self,
+        angle: f32,
+    ) -> i32
+    {
+        let steps = (angle - self.origin) * self.config.steps_per_degree();
+
+        match steps >= 0.0 {
+            true => (steps + 0.5) as i32,
+            false => (steps - 0.5) as i32,
+        }
+    }
+
+    /// Enable the driver and set the direction pin for a move to `target`
+    ///
+    /// Also brings the step pin low, in case a previous move was cancelled
+    /// mid-pulse, so that the first step of this move is a clean edge.
+    fn prepare(
+        &mut self,
+        target: i32,
+    ) -> Result<Direction, Step::Error>
+    {
+        let direction = match target > self.position {
+            true => Direction::Forward,
+            false => Direction::Reverse,
+        };
+
+        if !self.enabled {
+            self.enable()?;
+        }
+
+        self.step.set_low()?;
+
+        if self.direction != Some(direction) {
+            match (direction == Direction::Forward) != self.config.inverted {
+                true => self.dir.set_high(),
+                false => self.dir.set_low(),
+            }?;
+            self.direction = Some(direction);
+        }
+
+        Ok(direction)
+    }
+
+    /// Take a single step in the given direction
+    async fn pulse(
+        &mut self,
+        direction: Direction,
+    ) -> Result<(), Step::Error>
+    {
+        self.step.set_high()?;
+        self.position += match direction {
+            Direction::Forward => 1,
+            Direction::Reverse => -1,
+        };
+
+        Timer::after(self.config.pulse).await;
+        self.step.set_low()
+    }
+}
+
+impl<Step, Dir, Enable> Axis for Stepper<Step, Dir, Enable>
+where
+    Step: OutputPin,
+    Dir: OutputPin<Error = Step::Error>,
+    Enable: OutputPin<Error = Step::Error>,
+{
+    type Error = Step::Error;
+
+    /// Disable the driver, so the motor no longer holds its position
+    fn detach(&mut self) -> Result<(), Self::Error> { self.disable() }
+
+    /// Spread the steps to the set-point evenly over `within`
+    ///
+    /// The set-points of a [ServoPair](super::ServoPair) move already respect
+    /// its own motion limits, so no further ramping is added. Set-points that
+    /// cannot be reached within `within` at the configured maximum speed,
+    /// such as the pair's first move, are made as a standalone
+    /// [Stepper::move_to] instead.
+    async fn seek(
+        &mut self,
+        angle: f32,
+        within: Duration,
+    ) -> Result<(), Self::Error>
+    {
+        let target = self.steps_for(angle);
+        let steps = target.abs_diff(self.position);
+
+        if steps == 0 {
+            return Ok(());
+        }
+
+        let max_speed = self.config.kinematics.max_speed * self.config.steps_per_degree();
+        let within_seconds = within.as_micros() as f32 / 1_000_000.0;
+
+        if steps as f32 > max_speed * within_seconds {
+            return self.move_to(target).await;
+        }
+
+        let direction = self.prepare(target)?;
+        let interval = within / steps;
+        let start = Instant::now() + self.config.pulse;
+
+        for step in 0..steps {
+            Timer::at(start + interval * step).await;
+            self.pulse(direction).await?;
+        }
+        Ok(())
+    }
+}